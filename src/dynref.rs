@@ -0,0 +1,490 @@
+//! References whose lifetime is represented by a pinned [`Lifetime`] value.
+//!
+//! A [`DynRef`] starts empty. Locking it to a value returns a [`Lifetime`] to pin, and the
+//! `DynRef` refers to the value for as long as the pinned `Lifetime` lives. Transferring the
+//! `Lifetime` to an outer scope extends the reference accordingly.
+//!
+//! [`DynMut`] and [`LifetimeMut`] are the mutable counterparts of `DynRef` and `Lifetime`.
+//! [`AtomicDynRef`] and [`AtomicLifetime`] are their thread-safe counterparts.
+//!
+//! [`lock_all`] publishes a single value to several `DynRef`s, through a [`MultiLifetime`].
+//!
+//! The reference is only valid because the pinned `Lifetime` is dropped before the end of its
+//! borrow, so the lifetimes of this module must never reach an owner that can be leaked. Slots
+//! cannot be leaked, and the owners of this crate that can, such as
+//! [`transfer_to_box`](crate::transfer_to_box) or [`PinVec`](crate::vec::PinVec), only accept
+//! `'static` values.
+
+mod atomic;
+
+pub use atomic::{AtomicDynRef, AtomicLifetime, AtomicRef};
+
+use crate::Transfer;
+use stackpin::{FromUnpinned, PinStack, Unpinned};
+use std::cell::Cell;
+use std::marker::{PhantomData, PhantomPinned};
+use std::ops::Deref;
+use std::ptr;
+
+pub struct DynRef<T: ?Sized> {
+    ptr: Cell<Option<*const T>>,
+    borrows: Cell<usize>,
+}
+
+impl<T: ?Sized> DynRef<T> {
+    pub fn new() -> Self {
+        Self {
+            ptr: Cell::new(None),
+            borrows: Cell::new(0),
+        }
+    }
+
+    /// Returns a guard to the referred value, if any.
+    ///
+    /// The process aborts if the `Lifetime` of the value ends while a guard is still alive.
+    pub fn get(&self) -> Option<Ref<'_, T>> {
+        let ptr = self.ptr.get()?;
+        self.borrows.set(self.borrows.get() + 1);
+        Some(Ref {
+            dynref: self,
+            value: unsafe { &*ptr },
+        })
+    }
+
+    pub fn map<U, F: FnOnce(&T) -> U>(&self, f: F) -> Option<U> {
+        self.get().map(|value| f(&value))
+    }
+
+    pub fn with<U, F: FnOnce(Option<&T>) -> U>(&self, f: F) -> U {
+        let value = self.get();
+        f(value.as_deref())
+    }
+
+    pub fn is_some(&self) -> bool {
+        self.ptr.get().is_some()
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.ptr.get().is_none()
+    }
+
+    pub fn lock<'dr, 'br>(
+        &'dr self,
+        br: &'br T,
+    ) -> Unpinned<(&'br T, &'dr Self), Lifetime<'dr, 'br, T>> {
+        Unpinned::new((br, self))
+    }
+}
+
+impl<T: ?Sized> Default for DynRef<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A guard to the value referred by a `DynRef`, returned by `DynRef::get`.
+pub struct Ref<'a, T: ?Sized> {
+    dynref: &'a DynRef<T>,
+    value: &'a T,
+}
+
+impl<T: ?Sized> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: ?Sized> Drop for Ref<'_, T> {
+    fn drop(&mut self) {
+        let borrows = &self.dynref.borrows;
+        borrows.set(borrows.get() - 1);
+    }
+}
+
+struct Dropper<'dr, T: ?Sized + 'dr>(Option<&'dr DynRef<T>>);
+
+pub struct Lifetime<'dr, 'br, T: ?Sized + 'dr + 'br> {
+    dynref: Dropper<'dr, T>,
+    _data: PhantomData<&'br T>,
+    _pin: PhantomPinned,
+}
+
+impl<'dr, T: ?Sized + 'dr> Drop for Dropper<'dr, T> {
+    fn drop(&mut self) {
+        if let Some(dynref) = self.0 {
+            if dynref.borrows.get() != 0 {
+                // Unwinding would still invalidate the value that the guards refer to.
+                eprintln!("a `DynRef` was still borrowed at the end of its `Lifetime`, aborting");
+                std::process::abort();
+            }
+            dynref.ptr.set(None);
+        }
+    }
+}
+
+impl<'dr, 'br, T: ?Sized> Lifetime<'dr, 'br, T> {
+    fn new_empty() -> Self {
+        Self {
+            dynref: Dropper(None),
+            _data: PhantomData,
+            _pin: PhantomPinned,
+        }
+    }
+}
+
+unsafe impl<'dr, 'br, T: ?Sized> FromUnpinned<(&'br T, &'dr DynRef<T>)>
+    for Lifetime<'dr, 'br, T>
+{
+    type PinData = (&'br T, &'dr DynRef<T>);
+
+    unsafe fn from_unpinned(data: Self::PinData) -> (Self, Self::PinData) {
+        (Self::new_empty(), data)
+    }
+
+    unsafe fn on_pin(&mut self, (val, dynref): Self::PinData) {
+        let ptr = val as *const T;
+        dynref.ptr.set(Some(ptr));
+        self.dynref = Dropper(Some(dynref));
+    }
+}
+
+unsafe impl<'dr, 'br, T: ?Sized> Transfer for Lifetime<'dr, 'br, T> {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let mut transferred = Self::new_empty();
+        transferred.dynref.0 = src.as_mut().get_unchecked_mut().dynref.0.take();
+        ptr::write(dst, transferred);
+    }
+}
+
+/// Locks all the `dynrefs` to `br`, for as long as the returned `MultiLifetime` lives.
+pub fn lock_all<'dr, 'br, T: ?Sized, const N: usize>(
+    dynrefs: [&'dr DynRef<T>; N],
+    br: &'br T,
+) -> Unpinned<(&'br T, [&'dr DynRef<T>; N]), MultiLifetime<'dr, 'br, T, N>> {
+    Unpinned::new((br, dynrefs))
+}
+
+/// A [`Lifetime`] that publishes a value to `N` [`DynRef`]s at once, returned by [`lock_all`].
+pub struct MultiLifetime<'dr, 'br, T: ?Sized + 'dr + 'br, const N: usize> {
+    dynrefs: [Dropper<'dr, T>; N],
+    _data: PhantomData<&'br T>,
+    _pin: PhantomPinned,
+}
+
+impl<'dr, 'br, T: ?Sized, const N: usize> MultiLifetime<'dr, 'br, T, N> {
+    fn new_empty() -> Self {
+        Self {
+            dynrefs: [(); N].map(|_| Dropper(None)),
+            _data: PhantomData,
+            _pin: PhantomPinned,
+        }
+    }
+}
+
+unsafe impl<'dr, 'br, T: ?Sized, const N: usize> FromUnpinned<(&'br T, [&'dr DynRef<T>; N])>
+    for MultiLifetime<'dr, 'br, T, N>
+{
+    type PinData = (&'br T, [&'dr DynRef<T>; N]);
+
+    unsafe fn from_unpinned(data: Self::PinData) -> (Self, Self::PinData) {
+        (Self::new_empty(), data)
+    }
+
+    unsafe fn on_pin(&mut self, (val, dynrefs): Self::PinData) {
+        let ptr = val as *const T;
+        for (dropper, dynref) in self.dynrefs.iter_mut().zip(dynrefs.iter()) {
+            dynref.ptr.set(Some(ptr));
+            *dropper = Dropper(Some(dynref));
+        }
+    }
+}
+
+unsafe impl<'dr, 'br, T: ?Sized, const N: usize> Transfer for MultiLifetime<'dr, 'br, T, N> {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let mut transferred = Self::new_empty();
+        let src = src.as_mut().get_unchecked_mut();
+        for (dst, src) in transferred.dynrefs.iter_mut().zip(src.dynrefs.iter_mut()) {
+            dst.0 = src.0.take();
+        }
+        ptr::write(dst, transferred);
+    }
+}
+
+/// The mutable counterpart of [`DynRef`], whose value is borrowed through [`LifetimeMut`].
+///
+/// The value is only accessed through `with_mut`, which panics when called re-entrantly.
+pub struct DynMut<T: ?Sized> {
+    ptr: Cell<Option<*mut T>>,
+    borrowed: Cell<bool>,
+}
+
+impl<T: ?Sized> DynMut<T> {
+    pub fn new() -> Self {
+        Self {
+            ptr: Cell::new(None),
+            borrowed: Cell::new(false),
+        }
+    }
+
+    /// Calls `f` with exclusive access to the referred value, if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from inside the `f` of another `with_mut` call on the same `DynMut`.
+    ///
+    /// The process aborts if the `LifetimeMut` of the value ends while `f` runs.
+    pub fn with_mut<U, F: FnOnce(&mut T) -> U>(&self, f: F) -> Option<U> {
+        let ptr = self.ptr.get()?;
+        if self.borrowed.replace(true) {
+            panic!("`DynMut` already mutably borrowed");
+        }
+        let _guard = BorrowGuard(&self.borrowed);
+        Some(f(unsafe { &mut *ptr }))
+    }
+
+    pub fn is_borrowed(&self) -> bool {
+        self.borrowed.get()
+    }
+
+    pub fn is_some(&self) -> bool {
+        self.ptr.get().is_some()
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.ptr.get().is_none()
+    }
+
+    pub fn lock<'dm, 'br>(
+        &'dm self,
+        br: &'br mut T,
+    ) -> Unpinned<(&'br mut T, &'dm Self), LifetimeMut<'dm, 'br, T>> {
+        Unpinned::new((br, self))
+    }
+}
+
+impl<T: ?Sized> Default for DynMut<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Releases the borrow of a `DynMut`, including when `with_mut` unwinds.
+struct BorrowGuard<'a>(&'a Cell<bool>);
+
+impl Drop for BorrowGuard<'_> {
+    fn drop(&mut self) {
+        self.0.set(false)
+    }
+}
+
+struct DropperMut<'dm, T: ?Sized + 'dm>(Option<&'dm DynMut<T>>);
+
+pub struct LifetimeMut<'dm, 'br, T: ?Sized + 'dm + 'br> {
+    dynmut: DropperMut<'dm, T>,
+    _data: PhantomData<&'br mut T>,
+    _pin: PhantomPinned,
+}
+
+impl<'dm, T: ?Sized + 'dm> Drop for DropperMut<'dm, T> {
+    fn drop(&mut self) {
+        if let Some(dynmut) = self.0 {
+            if dynmut.borrowed.get() {
+                // Unwinding would still invalidate the value that `with_mut` is accessing.
+                eprintln!("a `DynMut` was still borrowed at the end of its `LifetimeMut`, aborting");
+                std::process::abort();
+            }
+            dynmut.ptr.set(None);
+        }
+    }
+}
+
+impl<'dm, 'br, T: ?Sized> LifetimeMut<'dm, 'br, T> {
+    fn new_empty() -> Self {
+        Self {
+            dynmut: DropperMut(None),
+            _data: PhantomData,
+            _pin: PhantomPinned,
+        }
+    }
+}
+
+unsafe impl<'dm, 'br, T: ?Sized> FromUnpinned<(&'br mut T, &'dm DynMut<T>)>
+    for LifetimeMut<'dm, 'br, T>
+{
+    type PinData = (&'br mut T, &'dm DynMut<T>);
+
+    unsafe fn from_unpinned(data: Self::PinData) -> (Self, Self::PinData) {
+        (Self::new_empty(), data)
+    }
+
+    unsafe fn on_pin(&mut self, (val, dynmut): Self::PinData) {
+        let ptr = val as *mut T;
+        dynmut.ptr.set(Some(ptr));
+        self.dynmut = DropperMut(Some(dynmut));
+    }
+}
+
+unsafe impl<'dm, 'br, T: ?Sized> Transfer for LifetimeMut<'dm, 'br, T> {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let mut transferred = Self::new_empty();
+        transferred.dynmut.0 = src.as_mut().get_unchecked_mut().dynmut.0.take();
+        ptr::write(dst, transferred);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lock_all, DynMut, DynRef};
+    use crate::transfer;
+    use stackpin::stack_let;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn lock_and_drop() {
+        let dr = DynRef::new();
+        assert!(dr.is_none());
+        {
+            let s = String::from("foo");
+            {
+                stack_let!(lifetime = dr.lock(&s));
+                assert!(dr.is_some());
+
+                // dropping the pin does not end the lifetime
+                {
+                    let _pin = lifetime;
+                }
+                assert!(dr.is_some());
+            }
+            assert!(dr.is_none());
+        }
+        assert_eq!(dr.map(|s| s.len()), None);
+    }
+
+    #[test]
+    fn access() {
+        let dr = DynRef::new();
+        let s = String::from("foo");
+        stack_let!(_lifetime = dr.lock(&s));
+        assert_eq!(dr.get().as_deref().map(String::as_str), Some("foo"));
+        assert_eq!(dr.map(|s| s.len()), Some(3));
+        assert!(dr.with(|s| s.is_some()));
+    }
+
+    fn transfer_if_odd(val: &'static str) -> bool {
+        let dr = DynRef::new();
+        {
+            let s = String::from(val);
+            crate::slot!(lifetime);
+            assert!(dr.is_none());
+            {
+                stack_let!(inner_lifetime = dr.lock(&s));
+                assert!(dr.is_some());
+                if val.len() % 2 == 1 {
+                    transfer(inner_lifetime, lifetime);
+                }
+                assert!(dr.is_some());
+            }
+            dr.map(|s| assert_eq!(s, val));
+            dr.is_some()
+        }
+    }
+
+    #[test]
+    fn transfer_out_of_inner_scope() {
+        assert!(transfer_if_odd("foo"));
+        assert!(!transfer_if_odd("foobar"));
+    }
+
+    #[test]
+    fn unsized_str_and_slice() {
+        let name = String::from("foo");
+        let bytes = [1u8, 2, 3];
+        let name_ref: DynRef<str> = DynRef::new();
+        let bytes_ref: DynRef<[u8]> = DynRef::new();
+        crate::slot!(name_lifetime);
+        {
+            stack_let!(inner_lifetime = name_ref.lock(name.as_str()));
+            transfer(inner_lifetime, name_lifetime);
+            stack_let!(_bytes_lifetime = bytes_ref.lock(&bytes[1..]));
+            assert_eq!(bytes_ref.map(|bytes| bytes.to_vec()), Some(vec![2, 3]));
+        }
+        assert!(bytes_ref.is_none());
+        assert_eq!(name_ref.map(str::len), Some(3));
+        assert_eq!(name_ref.get().as_deref(), Some("foo"));
+    }
+
+    #[test]
+    fn lock_all_observers() {
+        let observers = [DynRef::new(), DynRef::new(), DynRef::new()];
+        let [first, second, third] = &observers;
+        {
+            let event = String::from("event");
+            crate::slot!(lifetime);
+            {
+                stack_let!(inner_lifetime = lock_all([first, second, third], event.as_str()));
+                transfer(inner_lifetime, lifetime);
+            }
+            for observer in &observers {
+                assert_eq!(observer.map(str::len), Some(5));
+            }
+        }
+        assert!(observers.iter().all(DynRef::is_none));
+    }
+
+    #[test]
+    fn dynmut_with_mut() {
+        let dm = DynMut::new();
+        let mut counter = 0u32;
+        {
+            crate::slot!(lifetime);
+            {
+                stack_let!(inner_lifetime = dm.lock(&mut counter));
+                transfer(inner_lifetime, lifetime);
+            }
+            assert_eq!(dm.with_mut(|counter| *counter += 1), Some(()));
+            assert_eq!(dm.with_mut(|counter| *counter), Some(1));
+        }
+        assert!(dm.is_none());
+        assert_eq!(dm.with_mut(|counter| *counter), None);
+        assert_eq!(counter, 1);
+    }
+
+    #[test]
+    fn dynmut_reentrancy() {
+        let dm = DynMut::new();
+        let mut value = String::from("foo");
+        stack_let!(_lifetime = dm.lock(&mut value));
+        let nested = dm.with_mut(|_| {
+            assert!(dm.is_borrowed());
+            panic::catch_unwind(AssertUnwindSafe(|| dm.with_mut(|value| value.push('!'))))
+        });
+        assert!(nested.unwrap().is_err());
+        assert!(!dm.is_borrowed());
+        assert_eq!(dm.with_mut(|value| value.clone()).as_deref(), Some("foo"));
+    }
+
+    trait Handler {
+        fn handle(&self, event: u32) -> u32;
+    }
+
+    struct Double;
+
+    impl Handler for Double {
+        fn handle(&self, event: u32) -> u32 {
+            event * 2
+        }
+    }
+
+    #[test]
+    fn unsized_trait_object() {
+        let handler_ref: DynRef<dyn Handler> = DynRef::new();
+        let handler = Double;
+        {
+            stack_let!(_lifetime = handler_ref.lock(&handler));
+            assert_eq!(handler_ref.map(|handler| handler.handle(21)), Some(42));
+        }
+        assert!(handler_ref.is_none());
+    }
+}
@@ -0,0 +1,252 @@
+//! A thread-safe [`DynRef`](super::DynRef).
+
+use crate::Transfer;
+use stackpin::{FromUnpinned, PinStack, Unpinned};
+use std::cell::UnsafeCell;
+use std::marker::{PhantomData, PhantomPinned};
+use std::ops::Deref;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Set in the state while a writer sets or clears the pointer. The other bits count readers.
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+/// A `DynRef` that can be shared between threads.
+///
+/// Readers are counted, and the end of an `AtomicLifetime` waits until all the guards returned by
+/// `get` are dropped before invalidating the reference. Consequently, ending a lifetime on a
+/// thread that holds a guard to the same `AtomicDynRef` deadlocks.
+///
+/// Transferring an `AtomicLifetime` does not change the referred value, so it does not wait for
+/// readers.
+pub struct AtomicDynRef<T: ?Sized> {
+    state: AtomicUsize,
+    ptr: UnsafeCell<Option<*const T>>,
+}
+
+unsafe impl<T: ?Sized + Sync> Send for AtomicDynRef<T> {}
+unsafe impl<T: ?Sized + Sync> Sync for AtomicDynRef<T> {}
+
+impl<T: ?Sized> AtomicDynRef<T> {
+    pub fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            ptr: UnsafeCell::new(None),
+        }
+    }
+
+    /// Returns a guard to the referred value, if any.
+    ///
+    /// Waits if the reference is being set or cleared by another thread.
+    pub fn get(&self) -> Option<AtomicRef<'_, T>> {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & WRITER != 0 {
+                thread::yield_now();
+                state = self.state.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => state = current,
+            }
+        }
+        match unsafe { *self.ptr.get() } {
+            Some(ptr) => Some(AtomicRef {
+                dynref: self,
+                value: unsafe { &*ptr },
+            }),
+            None => {
+                self.state.fetch_sub(1, Ordering::Release);
+                None
+            }
+        }
+    }
+
+    pub fn map<U, F: FnOnce(&T) -> U>(&self, f: F) -> Option<U> {
+        self.get().map(|value| f(&value))
+    }
+
+    pub fn with<U, F: FnOnce(Option<&T>) -> U>(&self, f: F) -> U {
+        let value = self.get();
+        f(value.as_deref())
+    }
+
+    pub fn is_some(&self) -> bool {
+        self.map(|_| ()).is_some()
+    }
+
+    pub fn is_none(&self) -> bool {
+        !self.is_some()
+    }
+
+    pub fn lock<'dr, 'br>(
+        &'dr self,
+        br: &'br T,
+    ) -> Unpinned<(&'br T, &'dr Self), AtomicLifetime<'dr, 'br, T>> {
+        Unpinned::new((br, self))
+    }
+
+    /// Sets the pointer, once other writers are done and all readers released their guards.
+    fn write(&self, ptr: Option<*const T>) {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state & WRITER != 0 {
+                thread::yield_now();
+                state = self.state.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state | WRITER,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => state = current,
+            }
+        }
+        // No new reader can come in: wait for the current ones to leave.
+        while self.state.load(Ordering::Acquire) != WRITER {
+            thread::yield_now();
+        }
+        unsafe { *self.ptr.get() = ptr }
+        self.state.store(0, Ordering::Release);
+    }
+}
+
+impl<T: ?Sized> Default for AtomicDynRef<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A guard to the value referred by an `AtomicDynRef`, returned by `AtomicDynRef::get`.
+pub struct AtomicRef<'a, T: ?Sized> {
+    dynref: &'a AtomicDynRef<T>,
+    value: &'a T,
+}
+
+impl<T: ?Sized> Deref for AtomicRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: ?Sized> Drop for AtomicRef<'_, T> {
+    fn drop(&mut self) {
+        self.dynref.state.fetch_sub(1, Ordering::Release);
+    }
+}
+
+struct AtomicDropper<'dr, T: ?Sized + 'dr>(Option<&'dr AtomicDynRef<T>>);
+
+pub struct AtomicLifetime<'dr, 'br, T: ?Sized + 'dr + 'br> {
+    dynref: AtomicDropper<'dr, T>,
+    _data: PhantomData<&'br T>,
+    _pin: PhantomPinned,
+}
+
+impl<'dr, T: ?Sized + 'dr> Drop for AtomicDropper<'dr, T> {
+    fn drop(&mut self) {
+        if let Some(dynref) = self.0 {
+            dynref.write(None);
+        }
+    }
+}
+
+impl<'dr, 'br, T: ?Sized> AtomicLifetime<'dr, 'br, T> {
+    fn new_empty() -> Self {
+        Self {
+            dynref: AtomicDropper(None),
+            _data: PhantomData,
+            _pin: PhantomPinned,
+        }
+    }
+}
+
+unsafe impl<'dr, 'br, T: ?Sized> FromUnpinned<(&'br T, &'dr AtomicDynRef<T>)>
+    for AtomicLifetime<'dr, 'br, T>
+{
+    type PinData = (&'br T, &'dr AtomicDynRef<T>);
+
+    unsafe fn from_unpinned(data: Self::PinData) -> (Self, Self::PinData) {
+        (Self::new_empty(), data)
+    }
+
+    unsafe fn on_pin(&mut self, (val, dynref): Self::PinData) {
+        dynref.write(Some(val as *const T));
+        self.dynref = AtomicDropper(Some(dynref));
+    }
+}
+
+unsafe impl<'dr, 'br, T: ?Sized> Transfer for AtomicLifetime<'dr, 'br, T> {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let mut transferred = Self::new_empty();
+        transferred.dynref.0 = src.as_mut().get_unchecked_mut().dynref.0.take();
+        ptr::write(dst, transferred);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AtomicDynRef;
+    use crate::transfer;
+    use stackpin::stack_let;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn readers_on_other_threads() {
+        let dr = AtomicDynRef::new();
+        let value = String::from("shared");
+        thread::scope(|scope| {
+            crate::slot!(lifetime);
+            {
+                stack_let!(inner_lifetime = dr.lock(value.as_str()));
+                transfer(inner_lifetime, lifetime);
+            }
+            let readers: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| dr.map(str::len)))
+                .collect();
+            for reader in readers {
+                assert_eq!(reader.join().unwrap(), Some(6));
+            }
+        });
+        assert!(dr.is_none());
+    }
+
+    #[test]
+    fn lifetime_end_waits_for_readers() {
+        let dr = AtomicDynRef::new();
+        let released = AtomicBool::new(false);
+        let value = 42u64;
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            {
+                stack_let!(_lifetime = dr.lock(&value));
+                let (dr, released) = (&dr, &released);
+                scope.spawn(move || {
+                    let guard = dr.get().unwrap();
+                    sender.send(()).unwrap();
+                    thread::sleep(Duration::from_millis(50));
+                    assert_eq!(*guard, 42);
+                    released.store(true, Ordering::SeqCst);
+                });
+                receiver.recv().unwrap();
+            }
+            assert!(released.load(Ordering::SeqCst));
+            assert!(dr.is_none());
+        });
+    }
+}
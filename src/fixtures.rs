@@ -0,0 +1,97 @@
+//! Types shared by the tests of several modules.
+
+use crate::{pin_ptr, Transfer};
+use stackpin::{FromUnpinned, PinStack};
+use std::env;
+use std::marker::PhantomPinned;
+use std::ops::DerefMut;
+use std::pin::Pin;
+use std::process::Command;
+use std::ptr;
+
+/// Reborrows a pinned value, to transfer it and then check the reset source. Also pins the values
+/// that `stack_let!` cannot, such as options or tuples, once pinned with `std::pin::pin!`.
+pub(crate) fn pin_stack<P: DerefMut>(pinned: &mut Pin<P>) -> PinStack<'_, P::Target>
+where
+    P::Target: Sized,
+{
+    // The value stays pinned until it is dropped, as `pinned` is a pin.
+    unsafe { pin_ptr(pinned.as_mut().get_unchecked_mut()) }
+}
+
+/// Knows its own address, and checks that it was not moved.
+pub(crate) struct Tracked {
+    pub(crate) this: *const Tracked,
+    pub(crate) value: u32,
+    _pin: PhantomPinned,
+}
+
+impl Tracked {
+    /// An unpinned `Tracked`, that only knows its address once pinned or transferred.
+    pub(crate) fn new(value: u32) -> Self {
+        Self {
+            this: ptr::null(),
+            value,
+            _pin: PhantomPinned,
+        }
+    }
+
+    pub(crate) fn check(&self) -> u32 {
+        assert_eq!(self.this, self as *const Self);
+        self.value
+    }
+}
+
+unsafe impl FromUnpinned<u32> for Tracked {
+    type PinData = ();
+
+    unsafe fn from_unpinned(value: u32) -> (Self, ()) {
+        (Self::new(value), ())
+    }
+
+    unsafe fn on_pin(&mut self, _: ()) {
+        self.this = self as *const Self;
+    }
+}
+
+unsafe impl Transfer for Tracked {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let src = src.as_mut().get_unchecked_mut();
+        ptr::write(
+            dst,
+            Self {
+                this: dst,
+                value: src.value,
+                _pin: PhantomPinned,
+            },
+        );
+        src.value = 0;
+    }
+}
+
+/// The value given to `aborted_stderr`, in the child process it started.
+pub(crate) fn abort_child() -> Option<String> {
+    env::var("TRANSFER_ABORT_CHILD").ok()
+}
+
+/// Runs the test `name` in a child process, where `abort_child` returns `value`, checks that it
+/// aborted, and returns its stderr.
+pub(crate) fn aborted_stderr(name: &str, value: &str) -> String {
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["--exact", "--nocapture", name])
+        .env("TRANSFER_ABORT_CHILD", value)
+        .output()
+        .unwrap();
+    assert!(
+        !output.status.success(),
+        "{} did not abort for {}",
+        name,
+        value
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(output.status.signal(), Some(6));
+    }
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
@@ -0,0 +1,294 @@
+//! Calls to `Transfer` implementations that abort the process if the implementation panics.
+//!
+//! A panicking transfer leaves `dst` partially written and `src` not reset, and both would then
+//! be dropped during unwinding. Since neither can be recovered, every transfer made by this crate
+//! goes through these functions. Likewise, the reset sources that the crate drops in place are
+//! dropped by `drop_reset`, as unwinding would leave their storage neither dropped nor reusable.
+//! With the `debug-abort` feature, the type and the addresses involved are printed before
+//! aborting.
+//!
+//! With the `debug-checks` feature, these functions also check some points of the `Transfer`
+//! contract. They panic before the transfer if:
+//!
+//! * `src` and `dst` are the same instance
+//!
+//! and abort after the transfer, as `dst` may hold a value that would never be dropped, if:
+//!
+//! * `dst` was not written: it is filled with a poison pattern before the transfer, and the
+//!   check fails if all its bytes still have this pattern afterwards. Padding bytes are never
+//!   written by a transfer, so this is only checked for types that set `Transfer::NO_PADDING`,
+//!   and when the source does not have the poison pattern itself
+//! * `src` was not reset, according to `Transfer::is_reset`
+//!
+//! The transfers are reported to the [`hooks`](crate::hooks) once made.
+
+use crate::{hooks, Transfer, TryTransfer};
+use stackpin::PinStack;
+use std::any;
+use std::fmt;
+use std::mem;
+use std::process;
+use std::ptr;
+use std::slice;
+
+struct AbortOnUnwind<T> {
+    src: *const T,
+    /// `None` when dropping the reset source `src`.
+    dst: Option<*const T>,
+}
+
+impl<T> AbortOnUnwind<T> {
+    fn new(src: &PinStack<'_, T>, dst: *const T) -> Self {
+        Self {
+            src: &**src,
+            dst: Some(dst),
+        }
+    }
+}
+
+impl<T> Drop for AbortOnUnwind<T> {
+    fn drop(&mut self) {
+        if cfg!(feature = "debug-abort") {
+            match self.dst {
+                Some(dst) => eprintln!(
+                    "transfer of `{}` from {:p} to {:p} panicked, aborting",
+                    any::type_name::<T>(),
+                    self.src,
+                    dst
+                ),
+                None => eprintln!(
+                    "drop of the reset `{}` at {:p} panicked, aborting",
+                    any::type_name::<T>(),
+                    self.src
+                ),
+            }
+        }
+        process::abort();
+    }
+}
+
+const POISON: u8 = 0xa5;
+
+/// # Safety
+///
+/// All the bytes of `value` must be initialized.
+unsafe fn is_poison<T>(value: *const T) -> bool {
+    let bytes = slice::from_raw_parts(value as *const u8, mem::size_of::<T>());
+    bytes.iter().all(|&byte| byte == POISON)
+}
+
+/// Returns whether `dst` was poisoned, to be checked by `check_written`.
+unsafe fn check_before<T>(src: &PinStack<'_, T>, dst: *mut T, no_padding: bool) -> bool {
+    if !cfg!(feature = "debug-checks") {
+        return false;
+    }
+    assert!(
+        !ptr::eq(&**src, dst),
+        "transfer of `{}` from {:p} to itself",
+        any::type_name::<T>(),
+        dst
+    );
+    if !no_padding || is_poison::<T>(&**src) {
+        return false;
+    }
+    ptr::write_bytes(dst as *mut u8, POISON, mem::size_of::<T>());
+    true
+}
+
+/// Reports a violation found after the transfer. Unwinding would leave the value written to `dst`
+/// undropped, while it may already be linked to other values, so the process aborts.
+fn violation(message: fmt::Arguments<'_>) -> ! {
+    eprintln!("{}, aborting", message);
+    process::abort();
+}
+
+unsafe fn check_written<T>(dst: *mut T, poisoned: bool) {
+    if poisoned && is_poison(dst) {
+        violation(format_args!(
+            "transfer of `{}` did not write the destination at {:p}",
+            any::type_name::<T>(),
+            dst
+        ));
+    }
+}
+
+fn check_reset<T>(src: &PinStack<'_, T>, is_reset: fn(&T) -> bool) {
+    if cfg!(feature = "debug-checks") && !is_reset(src) {
+        violation(format_args!(
+            "transfer of `{}` did not reset the source at {:p}",
+            any::type_name::<T>(),
+            &**src
+        ));
+    }
+}
+
+/// `T::transfer`, aborting if it panics.
+pub(crate) unsafe fn transfer<T: Transfer>(src: &mut PinStack<'_, T>, dst: *mut T) {
+    let poisoned = check_before(src, dst, <T as Transfer>::NO_PADDING);
+    let guard = AbortOnUnwind::new(src, dst);
+    hooks::in_span::<T, _>(&**src, dst, || {
+        T::transfer(src, dst);
+        hooks::on_transfer::<T>(&**src, dst);
+    });
+    mem::forget(guard);
+    check_written(dst, poisoned);
+    check_reset(src, <T as Transfer>::is_reset);
+}
+
+/// `T::try_transfer`, aborting if it panics.
+pub(crate) unsafe fn try_transfer<T: TryTransfer>(
+    src: &mut PinStack<'_, T>,
+    dst: *mut T,
+) -> Result<(), T::Error> {
+    let poisoned = check_before(src, dst, <T as TryTransfer>::NO_PADDING);
+    let guard = AbortOnUnwind::new(src, dst);
+    let result = hooks::in_span::<T, _>(&**src, dst, || {
+        let result = T::try_transfer(src, dst);
+        if result.is_ok() {
+            hooks::on_transfer::<T>(&**src, dst);
+        }
+        result
+    });
+    mem::forget(guard);
+    if result.is_ok() {
+        check_written(dst, poisoned);
+        check_reset(src, <T as TryTransfer>::is_reset);
+    }
+    result
+}
+
+/// Drops the reset source at `src` in place, aborting if its destructor panics.
+///
+/// # Safety
+///
+/// Same as `ptr::drop_in_place`.
+pub(crate) unsafe fn drop_reset<T>(src: *mut T) {
+    let guard = AbortOnUnwind { src, dst: None };
+    ptr::drop_in_place(src);
+    mem::forget(guard);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{abort_child, aborted_stderr};
+    use crate::{pin_unpin, transfer, Transfer};
+    use stackpin::PinStack;
+
+    struct Panicking;
+
+    unsafe impl Transfer for Panicking {
+        unsafe fn transfer(_src: &mut PinStack<'_, Self>, _dst: *mut Self) {
+            panic!("broken transfer")
+        }
+    }
+
+    #[cfg(feature = "debug-checks")]
+    mod checks {
+        use crate::fixtures::{abort_child, aborted_stderr};
+        use crate::{pin_unpin, transfer, try_transfer, Transfer};
+        use stackpin::PinStack;
+        use std::ptr;
+
+        /// Not empty, so that the poison can be detected.
+        struct Unwritten(#[allow(dead_code)] u64);
+
+        unsafe impl Transfer for Unwritten {
+            unsafe fn transfer(_src: &mut PinStack<'_, Self>, _dst: *mut Self) {}
+
+            const NO_PADDING: bool = true;
+        }
+
+        struct Bytes([u8; 8]);
+
+        unsafe impl Transfer for Bytes {
+            unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+                ptr::write(dst, Self(src.0))
+            }
+
+            const NO_PADDING: bool = true;
+        }
+
+        struct Unreset(u64);
+
+        unsafe impl Transfer for Unreset {
+            unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+                ptr::write(dst, Self(src.0))
+            }
+
+            fn is_reset(&self) -> bool {
+                self.0 == 0
+            }
+        }
+
+        #[test]
+        #[cfg_attr(miri, ignore)]
+        fn unwritten_destination() {
+            if abort_child().is_some() {
+                let mut value = Unwritten(1);
+                crate::slot!(slot);
+                transfer(pin_unpin(&mut value), slot);
+                return;
+            }
+            let stderr = aborted_stderr("guard::tests::checks::unwritten_destination", "1");
+            assert!(stderr.contains("did not write the destination"));
+        }
+
+        #[test]
+        fn poison_pattern_is_written() {
+            let mut value = Bytes([0xa5; 8]);
+            crate::slot!(slot);
+            assert_eq!(transfer(pin_unpin(&mut value), slot).0, [0xa5; 8]);
+        }
+
+        #[test]
+        #[cfg_attr(miri, ignore)]
+        fn unreset_source() {
+            if abort_child().is_some() {
+                let mut value = Unreset(1);
+                crate::slot!(slot);
+                transfer(pin_unpin(&mut value), slot);
+                return;
+            }
+            let stderr = aborted_stderr("guard::tests::checks::unreset_source", "1");
+            assert!(stderr.contains("did not reset the source"));
+        }
+
+        #[test]
+        #[cfg_attr(miri, ignore)]
+        fn unreset_source_of_try_transfer() {
+            if abort_child().is_some() {
+                let mut value = Unreset(1);
+                crate::slot!(slot);
+                let _ = try_transfer(pin_unpin(&mut value), slot);
+                return;
+            }
+            let stderr =
+                aborted_stderr("guard::tests::checks::unreset_source_of_try_transfer", "1");
+            assert!(stderr.contains("did not reset the source"));
+        }
+
+        #[test]
+        fn reset_source() {
+            let mut value = Unreset(0);
+            crate::slot!(slot);
+            assert_eq!(transfer(pin_unpin(&mut value), slot).0, 0);
+        }
+    }
+
+    /// Runs in a child process, as the transfer aborts.
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn panicking_transfer_aborts() {
+        if abort_child().is_some() {
+            let mut value = Panicking;
+            crate::slot!(slot);
+            transfer(pin_unpin(&mut value), slot);
+            return;
+        }
+        let stderr = aborted_stderr("guard::tests::panicking_transfer_aborts", "1");
+        assert!(stderr.contains("broken transfer"));
+        if cfg!(feature = "debug-abort") {
+            assert!(stderr.contains("transfer of `transfer::guard::tests::Panicking` from"));
+        }
+    }
+}
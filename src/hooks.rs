@@ -0,0 +1,273 @@
+//! Observation of the transfers made by this crate.
+//!
+//! A hook registered with [`set_on_transfer`] is called after each transfer made by this crate,
+//! with the name of the transferred type and the addresses of the source and of the destination.
+//! Recording these calls allows to reconstruct the address history of a value when debugging
+//! relocation bugs. The hook is global: a hook interested in some types only can filter on the
+//! type name.
+//!
+//! Only the outermost transfer is reported: the transfers of the fields of a value, made by its
+//! `Transfer` implementation, are not.
+//!
+//! With the `trace` feature, each transfer also runs in a `tracing` span named `transfer`, with the
+//! `ty`, `src` and `dst` fields, and emits a `TRACE` event once the value is transferred. Transfers
+//! made while another transfer is in progress, such as in a `Transfer` implementation, are nested in
+//! its span.
+
+use std::any;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// The name of a transferred type, as returned by `std::any::type_name`.
+pub type TypeName = &'static str;
+
+/// Called with the name of the transferred type, the source and the destination of a transfer.
+pub type OnTransfer = fn(TypeName, *const (), *const ());
+
+static ON_TRANSFER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Calls `hook` after each transfer, replacing any previous hook.
+///
+/// The hook runs in the middle of the transfer: it **must not** panic, or the process is aborted.
+/// It also must not read through the pointers, as the source was reset and the destination could be
+/// dropped concurrently once the transfer returns.
+pub fn set_on_transfer(hook: OnTransfer) {
+    ON_TRANSFER.store(hook as *mut (), Ordering::Release);
+}
+
+/// Removes the hook set with [`set_on_transfer`], if any.
+pub fn clear_on_transfer() {
+    ON_TRANSFER.store(ptr::null_mut(), Ordering::Release);
+}
+
+/// Calls the hook, if any, for a transfer of a `T` from `src` to `dst`.
+pub(crate) fn on_transfer<T>(src: *const T, dst: *const T) {
+    #[cfg(feature = "trace")]
+    tracing::trace!("transferred");
+    let hook = ON_TRANSFER.load(Ordering::Acquire);
+    if !hook.is_null() {
+        // Only `set_on_transfer` stores non-null pointers, that are `OnTransfer` functions.
+        let hook: OnTransfer = unsafe { mem::transmute::<*mut (), OnTransfer>(hook) };
+        hook(any::type_name::<T>(), src as *const (), dst as *const ());
+    }
+}
+
+/// Runs `f`, the transfer of a `T` from `src` to `dst`, in its span.
+pub(crate) fn in_span<T, R>(src: *const T, dst: *const T, f: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "trace")]
+    {
+        let span = tracing::trace_span!(
+            "transfer",
+            ty = any::type_name::<T>(),
+            src = ?src,
+            dst = ?dst
+        );
+        span.in_scope(f)
+    }
+    #[cfg(not(feature = "trace"))]
+    {
+        let _ = (src, dst);
+        f()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TypeName;
+    use crate::{pin_unpin, transfer};
+    use std::sync::Mutex;
+
+    static TRANSFERS: Mutex<Vec<(TypeName, usize, usize)>> = Mutex::new(Vec::new());
+
+    fn record(ty: TypeName, src: *const (), dst: *const ()) {
+        if let Ok(mut transfers) = TRANSFERS.lock() {
+            transfers.push((ty, src as usize, dst as usize));
+        }
+    }
+
+    /// Only transferred by this test, as other tests can run while the hook is set.
+    #[derive(Default)]
+    struct Observed(u32);
+
+    impl crate::TriviallyTransferable for Observed {}
+
+    #[test]
+    fn hook_records_transfers() {
+        super::set_on_transfer(record);
+        let mut value = Observed(3);
+        let src = &value as *const Observed as usize;
+        crate::slot!(first);
+        let moved = transfer(pin_unpin(&mut value), first);
+        let mid = &*moved as *const Observed as usize;
+        crate::slot!(second);
+        let moved = transfer(moved, second);
+        let dst = &*moved as *const Observed as usize;
+        assert_eq!(moved.0, 3);
+        super::clear_on_transfer();
+
+        let transfers: Vec<_> = TRANSFERS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(ty, _, _)| *ty == std::any::type_name::<Observed>())
+            .map(|&(_, src, dst)| (src, dst))
+            .collect();
+        assert_eq!(transfers, [(src, mid), (mid, dst)]);
+    }
+
+    #[cfg(feature = "trace")]
+    mod trace {
+        use crate::{pin_unpin, transfer, Transfer};
+        use stackpin::PinStack;
+        use std::any;
+        use std::fmt::Debug;
+        use std::ptr;
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        /// A span or an event, with its fields and the index of the span it is nested in.
+        #[derive(Debug, PartialEq)]
+        struct Recorded {
+            name: &'static str,
+            fields: Vec<(&'static str, String)>,
+            parent: Option<usize>,
+        }
+
+        impl Visit for Recorded {
+            fn record_str(&mut self, field: &Field, value: &str) {
+                self.fields.push((field.name(), value.to_owned()));
+            }
+
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                self.fields.push((field.name(), format!("{:?}", value)));
+            }
+        }
+
+        #[derive(Default)]
+        struct Recording {
+            spans: Vec<Recorded>,
+            events: Vec<Recorded>,
+            /// The indices of the entered spans, innermost last.
+            entered: Vec<usize>,
+        }
+
+        impl Recording {
+            fn parent(&self, explicit: Option<&Id>, contextual: bool) -> Option<usize> {
+                match explicit {
+                    Some(id) => Some(id.into_u64() as usize - 1),
+                    None if contextual => self.entered.last().copied(),
+                    None => None,
+                }
+            }
+        }
+
+        /// Records the spans and events of the thread it is the default subscriber of.
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Recording>>);
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+                let mut recording = self.0.lock().unwrap();
+                let mut span = Recorded {
+                    name: attrs.metadata().name(),
+                    fields: Vec::new(),
+                    parent: recording.parent(attrs.parent(), attrs.is_contextual()),
+                };
+                attrs.record(&mut span);
+                recording.spans.push(span);
+                Id::from_u64(recording.spans.len() as u64)
+            }
+
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut recording = self.0.lock().unwrap();
+                let mut recorded = Recorded {
+                    name: "event",
+                    fields: Vec::new(),
+                    parent: recording.parent(event.parent(), event.is_contextual()),
+                };
+                event.record(&mut recorded);
+                recording.events.push(recorded);
+            }
+
+            fn enter(&self, span: &Id) {
+                let index = span.into_u64() as usize - 1;
+                self.0.lock().unwrap().entered.push(index);
+            }
+
+            fn exit(&self, _: &Id) {
+                self.0.lock().unwrap().entered.pop();
+            }
+        }
+
+        /// Transfers its field through a slot, so that the transfer of the field is nested in its
+        /// own.
+        #[derive(Default)]
+        struct Nesting(u32);
+
+        unsafe impl Transfer for Nesting {
+            unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+                let src = src.as_mut().get_unchecked_mut();
+                crate::slot!(slot);
+                let field = transfer(pin_unpin(&mut src.0), slot);
+                ptr::write(dst, Self(*field));
+            }
+        }
+
+        fn transfer_span(ty: &str, src: *const (), dst: Option<*const ()>) -> Vec<(&str, String)> {
+            let mut fields = vec![("ty", ty.to_owned()), ("src", format!("{:?}", src))];
+            fields.extend(dst.map(|dst| ("dst", format!("{:?}", dst))));
+            fields
+        }
+
+        #[test]
+        fn nested_spans() {
+            let recorder = Recorder::default();
+            let (src, dst) = tracing::subscriber::with_default(recorder.clone(), || {
+                let mut value = Nesting(5);
+                let src = &value as *const Nesting;
+                crate::slot!(slot);
+                let moved = transfer(pin_unpin(&mut value), slot);
+                assert_eq!(moved.0, 5);
+                (src as *const (), &*moved as *const Nesting as *const ())
+            });
+
+            let recording = recorder.0.lock().unwrap();
+            let spans: Vec<_> = recording
+                .spans
+                .iter()
+                .map(|span| (span.name, span.parent))
+                .collect();
+            assert_eq!(spans, [("transfer", None), ("transfer", Some(0))]);
+            let [outer, inner] = [&recording.spans[0], &recording.spans[1]];
+            assert_eq!(
+                outer.fields,
+                transfer_span(any::type_name::<Nesting>(), src, Some(dst))
+            );
+            // The slot of the field is local to the transfer of `Nesting`.
+            assert_eq!(
+                inner.fields[..2],
+                transfer_span(any::type_name::<u32>(), src, None)[..]
+            );
+            assert_eq!(inner.fields[2].0, "dst");
+
+            let transferred = |parent| Recorded {
+                name: "event",
+                fields: vec![("message", String::from("transferred"))],
+                parent: Some(parent),
+            };
+            assert_eq!(recording.events, [transferred(1), transferred(0)]);
+            assert!(recording.entered.is_empty());
+        }
+    }
+}
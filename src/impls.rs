@@ -0,0 +1,112 @@
+//! `Transfer` for the compositions of standard types: options, tuples and arrays.
+
+use crate::{guard, pin_ptr, Transfer};
+use stackpin::PinStack;
+use std::ptr;
+
+/// Transfers the contained value, if any, and leaves `None` in the source. The reset value is
+/// dropped in place, aborting if its destructor panics.
+unsafe impl<T: Transfer> Transfer for Option<T> {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let src = src.as_mut().get_unchecked_mut();
+        // Same approach as for derived enums: copy to get the right variant in the destination,
+        // then transfer the value over the copy without dropping it.
+        ptr::copy_nonoverlapping(src as *const Self, dst, 1);
+        if let (Some(src_value), Some(dst_value)) = (&mut *src, &mut *dst) {
+            T::transfer(&mut pin_ptr(src_value), dst_value);
+            guard::drop_reset(src_value);
+            ptr::write(src, None);
+        }
+    }
+
+    fn is_reset(&self) -> bool {
+        self.is_none()
+    }
+}
+
+unsafe impl<T: Transfer, const N: usize> Transfer for [T; N] {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let src = src.as_mut().get_unchecked_mut().as_mut_ptr();
+        let dst = dst as *mut T;
+        for i in 0..N {
+            T::transfer(&mut pin_ptr(src.add(i)), dst.add(i));
+        }
+    }
+
+    fn is_reset(&self) -> bool {
+        self.iter().all(T::is_reset)
+    }
+
+    const NO_PADDING: bool = T::NO_PADDING;
+}
+
+macro_rules! tuple_transfer {
+    ($($t:ident . $i:tt),+) => {
+        unsafe impl<$($t: Transfer),+> Transfer for ($($t,)+) {
+            unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+                let src = src.as_mut().get_unchecked_mut();
+                $($t::transfer(&mut pin_ptr(&mut src.$i), ptr::addr_of_mut!((*dst).$i));)+
+            }
+
+            fn is_reset(&self) -> bool {
+                $(self.$i.is_reset())&&+
+            }
+        }
+    };
+}
+
+tuple_transfer!(A.0);
+tuple_transfer!(A.0, B.1);
+tuple_transfer!(A.0, B.1, C.2);
+tuple_transfer!(A.0, B.1, C.2, D.3);
+tuple_transfer!(A.0, B.1, C.2, D.3, E.4);
+tuple_transfer!(A.0, B.1, C.2, D.3, E.4, F.5);
+tuple_transfer!(A.0, B.1, C.2, D.3, E.4, F.5, G.6);
+tuple_transfer!(A.0, B.1, C.2, D.3, E.4, F.5, G.6, H.7);
+tuple_transfer!(A.0, B.1, C.2, D.3, E.4, F.5, G.6, H.7, I.8);
+tuple_transfer!(A.0, B.1, C.2, D.3, E.4, F.5, G.6, H.7, I.8, J.9);
+tuple_transfer!(A.0, B.1, C.2, D.3, E.4, F.5, G.6, H.7, I.8, J.9, K.10);
+tuple_transfer!(A.0, B.1, C.2, D.3, E.4, F.5, G.6, H.7, I.8, J.9, K.10, L.11);
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{pin_stack, Tracked};
+    use crate::transfer;
+    use std::pin::pin;
+
+    #[test]
+    fn option() {
+        let mut some = pin!(Some(Tracked::new(4)));
+        crate::slot!(slot);
+        let moved = transfer(pin_stack(&mut some), slot);
+        assert_eq!((*moved).as_ref().unwrap().check(), 4);
+        assert!(some.is_none());
+
+        let mut none = pin!(None::<Tracked>);
+        crate::slot!(slot);
+        assert!(transfer(pin_stack(&mut none), slot).is_none());
+    }
+
+    #[test]
+    fn tuple() {
+        let mut tuple = pin!((Tracked::new(1), String::from("two"), Some(Tracked::new(3))));
+        crate::slot!(slot);
+        let moved = transfer(pin_stack(&mut tuple), slot);
+        assert_eq!(moved.0.check(), 1);
+        assert_eq!(moved.1, "two");
+        assert_eq!(moved.2.as_ref().unwrap().check(), 3);
+        assert_eq!(tuple.0.value, 0);
+        assert_eq!(tuple.1, "");
+        assert!(tuple.2.is_none());
+    }
+
+    #[test]
+    fn array() {
+        let mut array = pin!([Tracked::new(1), Tracked::new(2), Tracked::new(3)]);
+        crate::slot!(slot);
+        let moved = transfer(pin_stack(&mut array), slot);
+        let values: Vec<_> = moved.iter().map(Tracked::check).collect();
+        assert_eq!(values, [1, 2, 3]);
+        assert!(array.iter().all(|tracked| tracked.value == 0));
+    }
+}
@@ -0,0 +1,745 @@
+//! An intrusive doubly-linked list, whose nodes fix up their neighbors when transferred.
+//!
+//! A [`List`] does not own its nodes: each [`Link`] is pinned by its owner, for instance as a
+//! field of a larger pinned struct, and linked into a pinned `List`. Transferring a `Link`
+//! relinks its neighbors to its new address, and dropping a `Link` unlinks it. Likewise,
+//! transferring a `List` relinks its first and last nodes, and dropping a `List` unlinks all of
+//! its nodes.
+//!
+//! A [`Cursor`] gives access to the values of the nodes, and splices other lists at its position.
+//! The process aborts if a node is dropped or transferred while a cursor points to it.
+
+use crate::Transfer;
+use stackpin::{FromUnpinned, PinStack};
+use std::cell::Cell;
+use std::marker::{PhantomData, PhantomPinned};
+use std::pin::Pin;
+use std::ptr;
+
+/// The links shared by nodes and by the sentinel of a list.
+///
+/// An unlinked header has null pointers. A list is circular, and goes through the sentinel
+/// header of its `List`.
+struct Header {
+    prev: Cell<*const Header>,
+    next: Cell<*const Header>,
+    /// Whether this header is the sentinel of a `List`, rather than the header of a `Link`.
+    is_sentinel: bool,
+}
+
+impl Header {
+    const fn new(is_sentinel: bool) -> Self {
+        Self {
+            prev: Cell::new(ptr::null()),
+            next: Cell::new(ptr::null()),
+            is_sentinel,
+        }
+    }
+
+    fn is_linked(&self) -> bool {
+        !self.next.get().is_null()
+    }
+
+    /// Whether this header is linked to other headers than itself.
+    fn has_neighbors(&self) -> bool {
+        self.is_linked() && !ptr::eq(self.next.get(), self)
+    }
+
+    /// Links `this` between `prev` and `next`. The neighbors keep `this` as is, so the pointer to
+    /// the header of a `Link` must be made from the whole `Link`, for `Cursor` to read its value.
+    ///
+    /// # Safety
+    ///
+    /// `prev` and `next` must be adjacent headers of the same list, and `this` must be a valid,
+    /// unlinked header.
+    unsafe fn link_between(this: *const Header, prev: *const Header, next: *const Header) {
+        (*this).prev.set(prev);
+        (*this).next.set(next);
+        (*prev).next.set(this);
+        (*next).prev.set(this);
+    }
+
+    /// # Safety
+    ///
+    /// The neighbors of `self` must be valid.
+    unsafe fn unlink(&self) {
+        if !self.is_linked() {
+            return;
+        }
+        let (prev, next) = (self.prev.get(), self.next.get());
+        (*prev).next.set(next);
+        (*next).prev.set(prev);
+        self.prev.set(ptr::null());
+        self.next.set(ptr::null());
+    }
+
+    /// Moves all the nodes of the list whose sentinel is `sentinel` between `prev` and `next`.
+    ///
+    /// # Safety
+    ///
+    /// `sentinel` must be a linked sentinel, and `prev` and `next` adjacent headers of another list.
+    unsafe fn splice_between(sentinel: *const Header, prev: *const Header, next: *const Header) {
+        if !(*sentinel).has_neighbors() {
+            return;
+        }
+        let (first, last) = ((*sentinel).next.get(), (*sentinel).prev.get());
+        (*prev).next.set(first);
+        (*first).prev.set(prev);
+        (*last).next.set(next);
+        (*next).prev.set(last);
+        (*sentinel).prev.set(sentinel);
+        (*sentinel).next.set(sentinel);
+    }
+
+    /// Writes to `dst` a header that takes the place of `src` in its list, and unlinks `src`.
+    ///
+    /// # Safety
+    ///
+    /// The neighbors of `src` must be valid, and `dst` must be valid for writes.
+    unsafe fn relink(src: &Header, dst: *mut Header) {
+        ptr::write(dst, Header::new(src.is_sentinel));
+        if src.has_neighbors() {
+            Header::link_between(dst, src.prev.get(), src.next.get());
+        }
+        src.prev.set(ptr::null());
+        src.next.set(ptr::null());
+    }
+}
+
+/// A node of a [`List`], holding a value of type `T`.
+///
+/// An unlinked `Link` can be moved freely. Once linked, it must stay pinned, and can only be
+/// relocated with `Transfer`.
+#[repr(C)]
+pub struct Link<T> {
+    // must stay the first field, so that a `*const Header` to it is a `*const Link<T>`
+    header: Header,
+    /// Number of cursors pointing to this node.
+    cursors: Cell<usize>,
+    /// Only `None` after the value was transferred out.
+    value: Option<T>,
+    _pin: PhantomPinned,
+}
+
+impl<T> Link<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            header: Header::new(false),
+            cursors: Cell::new(0),
+            value: Some(value),
+            _pin: PhantomPinned,
+        }
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.header.is_linked()
+    }
+
+    pub fn get(&self) -> &T {
+        self.value.as_ref().expect("value of a transferred link")
+    }
+
+    /// Mutably borrows the value of an unlinked node. The list and its cursors can reach a linked
+    /// node, and would alias the returned reference.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this node is linked, or if a cursor points to it.
+    pub fn value_mut(self: Pin<&mut Self>) -> &mut T {
+        assert!(!self.is_linked(), "value of a linked link");
+        assert_eq!(self.cursors.get(), 0, "link borrowed by a cursor");
+        let this = unsafe { self.get_unchecked_mut() };
+        this.value.as_mut().expect("value of a transferred link")
+    }
+
+    /// The header of this node, as a pointer to the whole node.
+    fn header_ptr(self: Pin<&Self>) -> *const Header {
+        // `header` is the first field of this `repr(C)` struct.
+        self.get_ref() as *const Self as *const Header
+    }
+
+    /// Removes this node from its list, if any.
+    pub fn unlink(&self) {
+        unsafe { self.header.unlink() }
+    }
+
+    fn check_unborrowed(&self) {
+        if self.cursors.get() != 0 {
+            // Neither unwinding nor continuing would keep the cursor valid.
+            eprintln!("a `Link` was dropped or transferred while a cursor pointed to it, aborting");
+            std::process::abort();
+        }
+    }
+}
+
+unsafe impl<T> FromUnpinned<T> for Link<T> {
+    type PinData = ();
+
+    unsafe fn from_unpinned(value: T) -> (Self, ()) {
+        (Self::new(value), ())
+    }
+
+    unsafe fn on_pin(&mut self, _: ()) {}
+}
+
+unsafe impl<T> Transfer for Link<T> {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let src = src.as_mut().get_unchecked_mut();
+        src.check_unborrowed();
+        ptr::write(
+            dst,
+            Self {
+                header: Header::new(false),
+                cursors: Cell::new(0),
+                value: src.value.take(),
+                _pin: PhantomPinned,
+            },
+        );
+        Header::relink(&src.header, ptr::addr_of_mut!((*dst).header));
+    }
+
+    fn is_reset(&self) -> bool {
+        !self.is_linked() && self.value.is_none()
+    }
+}
+
+impl<T> Drop for Link<T> {
+    fn drop(&mut self) {
+        self.check_unborrowed();
+        self.unlink();
+    }
+}
+
+/// A doubly-linked list of pinned [`Link`]s.
+pub struct List<T> {
+    sentinel: Header,
+    _marker: PhantomData<*const Link<T>>,
+    _pin: PhantomPinned,
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        Self {
+            sentinel: Header::new(true),
+            _marker: PhantomData,
+            _pin: PhantomPinned,
+        }
+    }
+
+    /// Returns the sentinel, linked to itself if the list is empty.
+    fn sentinel(self: Pin<&Self>) -> *const Header {
+        let sentinel = &self.get_ref().sentinel;
+        if !sentinel.is_linked() {
+            sentinel.prev.set(sentinel);
+            sentinel.next.set(sentinel);
+        }
+        sentinel
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.sentinel.has_neighbors()
+    }
+
+    /// Counts the nodes of the list.
+    pub fn len(self: Pin<&Self>) -> usize {
+        let sentinel = self.sentinel();
+        let mut len = 0;
+        let mut current = unsafe { (*sentinel).next.get() };
+        while current != sentinel {
+            len += 1;
+            current = unsafe { (*current).next.get() };
+        }
+        len
+    }
+
+    /// Links `node` at the front of the list, unlinking it from its previous list if any.
+    pub fn push_front(self: Pin<&Self>, node: Pin<&Link<T>>) {
+        let sentinel = self.sentinel();
+        node.unlink();
+        unsafe { Header::link_between(node.header_ptr(), sentinel, (*sentinel).next.get()) }
+    }
+
+    /// Links `node` at the back of the list, unlinking it from its previous list if any.
+    pub fn push_back(self: Pin<&Self>, node: Pin<&Link<T>>) {
+        let sentinel = self.sentinel();
+        node.unlink();
+        unsafe { Header::link_between(node.header_ptr(), (*sentinel).prev.get(), sentinel) }
+    }
+
+    /// Unlinks the first node of the list, returning whether there was one.
+    pub fn pop_front(self: Pin<&Self>) -> bool {
+        let sentinel = self.sentinel();
+        self.pop(unsafe { (*sentinel).next.get() })
+    }
+
+    /// Unlinks the last node of the list, returning whether there was one.
+    pub fn pop_back(self: Pin<&Self>) -> bool {
+        let sentinel = self.sentinel();
+        self.pop(unsafe { (*sentinel).prev.get() })
+    }
+
+    fn pop(self: Pin<&Self>, node: *const Header) -> bool {
+        if node == self.sentinel() {
+            return false;
+        }
+        unsafe { (*node).unlink() };
+        true
+    }
+
+    /// Moves all the nodes of `other` to the back of this list.
+    ///
+    /// [`Cursor::splice_before`] and [`Cursor::splice_after`] move them to other positions.
+    pub fn append(self: Pin<&Self>, other: Pin<&Self>) {
+        let (sentinel, other_sentinel) = (self.sentinel(), other.sentinel());
+        if sentinel == other_sentinel {
+            return;
+        }
+        unsafe { Header::splice_between(other_sentinel, (*sentinel).prev.get(), sentinel) }
+    }
+
+    /// Returns a cursor to the first node of the list.
+    pub fn cursor_front(self: Pin<&Self>) -> Cursor<'_, T> {
+        let mut cursor = Cursor::new(self);
+        cursor.move_next();
+        cursor
+    }
+
+    /// Returns a cursor to the last node of the list.
+    pub fn cursor_back(self: Pin<&Self>) -> Cursor<'_, T> {
+        let mut cursor = Cursor::new(self);
+        cursor.move_prev();
+        cursor
+    }
+
+    /// Calls `f` on the value of each node, from front to back.
+    pub fn for_each<F: FnMut(&T)>(self: Pin<&Self>, mut f: F) {
+        let mut cursor = self.cursor_front();
+        while let Some(value) = cursor.current() {
+            f(value);
+            cursor.move_next();
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<T> FromUnpinned<()> for List<T> {
+    type PinData = ();
+
+    unsafe fn from_unpinned(_: ()) -> (Self, ()) {
+        (Self::new(), ())
+    }
+
+    unsafe fn on_pin(&mut self, _: ()) {}
+}
+
+unsafe impl<T> Transfer for List<T> {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let src = src.as_mut().get_unchecked_mut();
+        ptr::write(dst, Self::new());
+        Header::relink(&src.sentinel, ptr::addr_of_mut!((*dst).sentinel));
+    }
+
+    fn is_reset(&self) -> bool {
+        !self.sentinel.is_linked()
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        if !self.sentinel.is_linked() {
+            return;
+        }
+        let sentinel = &self.sentinel as *const Header;
+        let mut current = self.sentinel.next.get();
+        while current != sentinel {
+            unsafe {
+                let next = (*current).next.get();
+                (*current).prev.set(ptr::null());
+                (*current).next.set(ptr::null());
+                current = next;
+            }
+        }
+    }
+}
+
+/// A position in a [`List`], either on a node or on the "ghost" position between the last and the
+/// first nodes.
+///
+/// If the current node is moved to another list, the cursor follows it, and goes back to the ghost
+/// position of its own list when it reaches the end of the other list.
+pub struct Cursor<'a, T> {
+    list: Pin<&'a List<T>>,
+    current: *const Header,
+}
+
+impl<'a, T> Cursor<'a, T> {
+    fn new(list: Pin<&'a List<T>>) -> Self {
+        Self {
+            current: list.sentinel(),
+            list,
+        }
+    }
+
+    fn node(&self) -> Option<&Link<T>> {
+        if unsafe { (*self.current).is_sentinel } {
+            None
+        } else {
+            Some(unsafe { &*(self.current as *const Link<T>) })
+        }
+    }
+
+    /// Moves to `current`, that must be a valid header.
+    fn set(&mut self, current: *const Header) {
+        if let Some(node) = self.node() {
+            node.cursors.set(node.cursors.get() - 1);
+        }
+        // The current node can be moved to another list, with `List::append` or by pushing it to
+        // the other list. Reaching the sentinel of the other list moves to the ghost position of
+        // this list instead, as the other list could be dropped while the cursor is on it.
+        self.current = if unsafe { (*current).is_sentinel } {
+            self.list.sentinel()
+        } else {
+            current
+        };
+        if let Some(node) = self.node() {
+            node.cursors.set(node.cursors.get() + 1);
+        }
+    }
+
+    /// The value of the current node, or `None` on the ghost position.
+    pub fn current(&self) -> Option<&T> {
+        self.node().map(Link::get)
+    }
+
+    /// Moves to the next node. The ghost position moves to the first node.
+    ///
+    /// If the current node was unlinked, moves to the ghost position.
+    pub fn move_next(&mut self) {
+        let next = unsafe { (*self.current).next.get() };
+        self.set(if next.is_null() {
+            self.list.sentinel()
+        } else {
+            next
+        })
+    }
+
+    /// Moves to the previous node. The ghost position moves to the last node.
+    ///
+    /// If the current node was unlinked, moves to the ghost position.
+    pub fn move_prev(&mut self) {
+        let prev = unsafe { (*self.current).prev.get() };
+        self.set(if prev.is_null() {
+            self.list.sentinel()
+        } else {
+            prev
+        })
+    }
+
+    /// Moves all the nodes of `other` after the current node. The ghost position moves them to the
+    /// front of the list.
+    ///
+    /// If the current node was unlinked, splices at the ghost position. Does nothing if `other` is
+    /// the list of the current node.
+    pub fn splice_after(&mut self, other: Pin<&List<T>>) {
+        if let Some((current, sentinel)) = self.splice_point(other) {
+            unsafe { Header::splice_between(sentinel, current, (*current).next.get()) }
+        }
+    }
+
+    /// Moves all the nodes of `other` before the current node. The ghost position moves them to
+    /// the back of the list.
+    ///
+    /// If the current node was unlinked, splices at the ghost position. Does nothing if `other` is
+    /// the list of the current node.
+    pub fn splice_before(&mut self, other: Pin<&List<T>>) {
+        if let Some((current, sentinel)) = self.splice_point(other) {
+            unsafe { Header::splice_between(sentinel, (*current).prev.get(), current) }
+        }
+    }
+
+    /// Returns the header to splice `other` next to, and the sentinel of `other`, unless `other`
+    /// is the list of that header.
+    fn splice_point(&self, other: Pin<&List<T>>) -> Option<(*const Header, *const Header)> {
+        let other = other.sentinel();
+        let current = if unsafe { (*self.current).is_linked() } {
+            self.current
+        } else {
+            self.list.sentinel()
+        };
+        // The current node can be in another list than this one, so its list is found by walking
+        // to a sentinel.
+        let mut sentinel = current;
+        while !unsafe { (*sentinel).is_sentinel } {
+            sentinel = unsafe { (*sentinel).next.get() };
+        }
+        if sentinel == other {
+            None
+        } else {
+            Some((current, other))
+        }
+    }
+
+    /// Unlinks the current node and moves to the next one. Does nothing on the ghost position.
+    pub fn unlink_current(&mut self) {
+        if self.node().is_none() {
+            return;
+        }
+        let next = unsafe { (*self.current).next.get() };
+        let current = self.current;
+        self.set(next);
+        unsafe { (*current).unlink() }
+    }
+}
+
+impl<T> Drop for Cursor<'_, T> {
+    fn drop(&mut self) {
+        self.set(self.list.sentinel())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Link, List};
+    use crate::transfer;
+    use stackpin::stack_let;
+    use std::pin::Pin;
+
+    mod conformance {
+        use super::Link;
+        use crate::testing::DropToken;
+        use crate::Tr;
+        use stackpin::PinStack;
+        use std::pin::Pin;
+
+        type Checked = Link<(u32, DropToken)>;
+
+        fn generate(slot: Pin<&mut Tr<Checked>>, token: DropToken) -> PinStack<'_, Checked> {
+            slot.emplace((7, token))
+        }
+
+        crate::check_transfer!(Checked, generate, |link: &Checked| link.get().0);
+    }
+
+    fn values(list: Pin<&List<u32>>) -> Vec<u32> {
+        let mut values = Vec::new();
+        list.for_each(|value| values.push(*value));
+        values
+    }
+
+    #[test]
+    fn push_and_pop() {
+        stack_let!(list: List<u32> = ());
+        stack_let!(one: Link<u32> = 1);
+        stack_let!(two: Link<u32> = 2);
+        stack_let!(three: Link<u32> = 3);
+        list.as_ref().push_back(one.as_ref());
+        list.as_ref().push_back(two.as_ref());
+        list.as_ref().push_front(three.as_ref());
+        assert_eq!(values(list.as_ref()), [3, 1, 2]);
+        assert_eq!(list.as_ref().len(), 3);
+
+        assert!(list.as_ref().pop_front());
+        assert!(!three.is_linked());
+        assert!(list.as_ref().pop_back());
+        assert_eq!(values(list.as_ref()), [1]);
+        assert!(list.as_ref().pop_back());
+        assert!(!list.as_ref().pop_back());
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn drop_unlinks() {
+        stack_let!(list: List<u32> = ());
+        stack_let!(one: Link<u32> = 1);
+        list.as_ref().push_back(one.as_ref());
+        {
+            stack_let!(two: Link<u32> = 2);
+            list.as_ref().push_back(two.as_ref());
+            assert_eq!(values(list.as_ref()), [1, 2]);
+        }
+        assert_eq!(values(list.as_ref()), [1]);
+    }
+
+    #[test]
+    fn value_mut_of_unlinked_node() {
+        stack_let!(list: List<u32> = ());
+        stack_let!(one: Link<u32> = 1);
+        let mut one = one;
+        *one.as_mut().value_mut() += 1;
+        list.as_ref().push_back(one.as_ref());
+        assert_eq!(values(list.as_ref()), [2]);
+        assert!(list.as_ref().pop_front());
+        *one.as_mut().value_mut() += 1;
+        assert_eq!(*one.get(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "value of a linked link")]
+    fn value_mut_of_linked_node() {
+        stack_let!(list: List<u32> = ());
+        stack_let!(one: Link<u32> = 1);
+        let mut one = one;
+        list.as_ref().push_back(one.as_ref());
+        one.as_mut().value_mut();
+    }
+
+    #[test]
+    fn transfer_relinks_neighbors() {
+        stack_let!(list: List<u32> = ());
+        stack_let!(one: Link<u32> = 1);
+        stack_let!(three: Link<u32> = 3);
+        list.as_ref().push_back(one.as_ref());
+        crate::slot!(outer);
+        {
+            stack_let!(two: Link<u32> = 2);
+            list.as_ref().push_back(two.as_ref());
+            list.as_ref().push_back(three.as_ref());
+            transfer(two, outer);
+        }
+        assert_eq!(values(list.as_ref()), [1, 2, 3]);
+        let mut cursor = list.as_ref().cursor_back();
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&2));
+    }
+
+    #[test]
+    fn transfer_list() {
+        stack_let!(one: Link<u32> = 1);
+        stack_let!(two: Link<u32> = 2);
+        {
+            crate::slot!(outer);
+            let list = {
+                stack_let!(list: List<u32> = ());
+                list.as_ref().push_back(one.as_ref());
+                list.as_ref().push_back(two.as_ref());
+                transfer(list, outer)
+            };
+            assert_eq!(values(list.as_ref()), [1, 2]);
+        }
+        assert!(!one.is_linked());
+        assert!(!two.is_linked());
+    }
+
+    #[test]
+    fn append_and_cursor() {
+        stack_let!(first: List<u32> = ());
+        stack_let!(second: List<u32> = ());
+        stack_let!(one: Link<u32> = 1);
+        stack_let!(two: Link<u32> = 2);
+        stack_let!(three: Link<u32> = 3);
+        first.as_ref().push_back(one.as_ref());
+        second.as_ref().push_back(two.as_ref());
+        second.as_ref().push_back(three.as_ref());
+        first.as_ref().append(second.as_ref());
+        assert!(second.is_empty());
+        assert_eq!(values(first.as_ref()), [1, 2, 3]);
+
+        let mut cursor = first.as_ref().cursor_front();
+        cursor.move_next();
+        cursor.unlink_current();
+        assert_eq!(cursor.current(), Some(&3));
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&1));
+        drop(cursor);
+        assert_eq!(values(first.as_ref()), [1, 3]);
+    }
+
+    #[test]
+    fn cursor_on_appended_node() {
+        stack_let!(first: List<u32> = ());
+        stack_let!(second: List<u32> = ());
+        stack_let!(one: Link<u32> = 1);
+        stack_let!(two: Link<u32> = 2);
+        first.as_ref().push_back(one.as_ref());
+        second.as_ref().push_back(two.as_ref());
+
+        let mut cursor = second.as_ref().cursor_back();
+        first.as_ref().append(second.as_ref());
+        assert_eq!(cursor.current(), Some(&2));
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        drop(cursor);
+        assert_eq!(values(first.as_ref()), [1, 2]);
+    }
+
+    #[test]
+    fn splice_at_cursor() {
+        stack_let!(first: List<u32> = ());
+        stack_let!(second: List<u32> = ());
+        stack_let!(one: Link<u32> = 1);
+        stack_let!(two: Link<u32> = 2);
+        stack_let!(three: Link<u32> = 3);
+        stack_let!(four: Link<u32> = 4);
+        first.as_ref().push_back(one.as_ref());
+        first.as_ref().push_back(four.as_ref());
+        second.as_ref().push_back(two.as_ref());
+
+        let mut cursor = first.as_ref().cursor_front();
+        cursor.splice_after(second.as_ref());
+        assert!(second.is_empty());
+        assert_eq!(cursor.current(), Some(&1));
+        second.as_ref().push_back(three.as_ref());
+        cursor.move_next();
+        cursor.move_next();
+        cursor.splice_before(second.as_ref());
+        assert_eq!(cursor.current(), Some(&4));
+        cursor.splice_before(first.as_ref());
+        drop(cursor);
+        assert_eq!(values(first.as_ref()), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn splice_at_ghost_position() {
+        stack_let!(first: List<u32> = ());
+        stack_let!(second: List<u32> = ());
+        stack_let!(one: Link<u32> = 1);
+        stack_let!(two: Link<u32> = 2);
+        stack_let!(three: Link<u32> = 3);
+        first.as_ref().push_back(two.as_ref());
+        second.as_ref().push_back(one.as_ref());
+
+        let mut cursor = first.as_ref().cursor_back();
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        cursor.splice_after(second.as_ref());
+        second.as_ref().push_back(three.as_ref());
+        cursor.splice_before(second.as_ref());
+        assert_eq!(cursor.current(), None);
+        drop(cursor);
+        assert_eq!(values(first.as_ref()), [1, 2, 3]);
+    }
+
+    #[test]
+    fn cursor_on_node_pushed_to_another_list() {
+        stack_let!(first: List<u32> = ());
+        stack_let!(second: List<u32> = ());
+        stack_let!(one: Link<u32> = 1);
+        stack_let!(two: Link<u32> = 2);
+        stack_let!(three: Link<u32> = 3);
+        first.as_ref().push_back(one.as_ref());
+        first.as_ref().push_back(two.as_ref());
+        second.as_ref().push_back(three.as_ref());
+
+        let mut cursor = first.as_ref().cursor_front();
+        assert!(first.as_ref().pop_front());
+        second.as_ref().push_front(one.as_ref());
+        assert_eq!(cursor.current(), Some(&1));
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&3));
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&2));
+        drop(cursor);
+        assert_eq!(values(second.as_ref()), [1, 3]);
+    }
+}
@@ -1,77 +1,482 @@
-use stackpin::PinStack;
+use stackpin::{FromUnpinned, PinStack, StackPinned};
+use std::convert::Infallible;
+use std::marker::{PhantomData, PhantomPinned};
+use std::mem::MaybeUninit;
+use std::pin::Pin;
+use std::ptr;
+
+#[cfg(feature = "derive")]
+pub use transfer_derive::{returns_pinned, Transfer};
+
+#[cfg(test)]
+extern crate self as transfer;
+
+#[cfg(test)]
+mod fixtures;
+mod guard;
+mod impls;
+
+pub mod dynref;
+pub mod hooks;
+pub mod intrusive;
+pub mod secret;
+pub mod selfref;
+pub mod testing;
+pub mod vec;
 
 ///
 /// # Safety
 ///
 /// * Implementers **must** write a valid `Self` to the `dst` argument of `transfer`
-/// * Implementers are **not** allowed to panic in the `transfer` function
+/// * Implementers are **not** allowed to panic in the `transfer` function. The functions of this
+///   crate abort the process if they do.
 /// * Implementers **must** reset `pin` to a value that can be safely dropped without incidence on
 ///   the `dst` pointer that was written to in the `transfer` function
+/// * Implementers **must not** set `NO_PADDING` if `Self` has padding bytes
 pub unsafe trait Transfer {
     /// # Safety
     ///
-    /// * Callers of this function **must** call `reset` on the `src` argument right afterwards.
-    /// * `dst` must point to a `Self` instance, that can possibly be uninitialized
+    /// * `dst` must point to storage for a `Self` instance, that can possibly be uninitialized.
+    ///   Implementers must neither read nor drop the previous contents of `dst`.
     /// * `src` and `dest` **must** point to different instances.
     unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self)
     where
         Self: Sized;
 
-    fn empty() -> Tr<Self>;
+    /// Whether this value is in the state that `transfer` leaves the source in.
+    ///
+    /// Only checked on the source after each transfer with the `debug-checks` feature.
+    fn is_reset(&self) -> bool {
+        true
+    }
+
+    /// Whether all the bytes of `Self` are initialized, so that the `debug-checks` feature can
+    /// check that `transfer` wrote the destination.
+    ///
+    /// It is set for [`Secret`](secret::Secret), for the arrays of types that set it, and derived
+    /// for structs whose fields set it and leave no padding.
+    const NO_PADDING: bool = false;
+}
+
+/// Uninitialized storage that a value can be transferred to.
+///
+/// A `Tr` only drops its contents if a value was actually transferred to it. Slots are passed as
+/// `Pin<&mut Tr<T>>`, so that a value transferred to a slot is not moved with it. A slot also must
+/// not be forgotten, so creating one is unsafe: `slot!` and `transfer_let!` declare slots that
+/// cannot be misused.
+pub struct Tr<T> {
+    slot: MaybeUninit<T>,
+    init: bool,
+}
+
+impl<T> Tr<T> {
+    /// Creates an empty slot.
+    ///
+    /// # Safety
+    ///
+    /// Once a value was transferred to the slot or emplaced in it, the slot **must not** be moved
+    /// or forgotten until it is dropped, as the value may rely on the drop guarantee of `Pin`.
+    /// `slot!` and `transfer_let!` uphold this by shadowing the slot with a pinned reference to it.
+    pub unsafe fn uninit() -> Self {
+        Self {
+            slot: MaybeUninit::uninit(),
+            init: false,
+        }
+    }
+
+    /// Constructs a value from `data` directly in the slot, without transferring it.
+    ///
+    /// Runs `FromUnpinned::from_unpinned`, then `FromUnpinned::on_pin` at the address of the slot.
+    /// Any value previously in the slot is dropped first.
+    pub fn emplace<S>(self: Pin<&mut Self>, data: S) -> PinStack<'_, T>
+    where
+        T: FromUnpinned<S>,
+    {
+        unsafe {
+            let this = self.get_unchecked_mut();
+            let slot = this.slot();
+            let (value, pin_data) = T::from_unpinned(data);
+            ptr::write(slot, value);
+            this.init = true;
+            (*slot).on_pin(pin_data);
+            pin_ptr(slot)
+        }
+    }
+
+    /// Drops the value previously transferred to the slot, if any, and returns a pointer to the
+    /// uninitialized slot.
+    ///
+    /// # Safety
+    ///
+    /// The caller must set `init` once it has written a value to the slot.
+    unsafe fn slot(&mut self) -> *mut T {
+        if self.init {
+            self.init = false;
+            ptr::drop_in_place(self.slot.as_mut_ptr());
+        }
+        self.slot.as_mut_ptr()
+    }
+}
+
+impl<T> Drop for Tr<T> {
+    fn drop(&mut self) {
+        if self.init {
+            unsafe { ptr::drop_in_place(self.slot.as_mut_ptr()) }
+        }
+    }
+}
+
+unsafe impl Transfer for PhantomPinned {
+    unsafe fn transfer(_src: &mut PinStack<'_, Self>, _dst: *mut Self) {}
+}
+
+unsafe impl<T: ?Sized> Transfer for PhantomData<T> {
+    unsafe fn transfer(_src: &mut PinStack<'_, Self>, _dst: *mut Self) {}
+}
+
+/// Movable types, that are transferred by moving them and resetting the source to its default.
+///
+/// Implementing this trait provides `Transfer`, so that the value can be used with the
+/// functions and containers of this crate. `Default::default` **should not** panic, as it is
+/// called during the transfer.
+///
+/// It is implemented for the primitive types, `String` and `Vec<T: Unpin>`.
+pub trait TriviallyTransferable: Unpin + Default {}
+
+macro_rules! trivially_transferable {
+    ($($t:ty),*) => {
+        $(impl TriviallyTransferable for $t {})*
+    };
+}
+
+trivially_transferable!(
+    bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, (),
+    String
+);
+
+impl<T: Unpin> TriviallyTransferable for Vec<T> {}
+
+unsafe impl<T: TriviallyTransferable> Transfer for T {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        ptr::write(dst, std::mem::take(&mut **src))
+    }
+}
+
+/// Pins a movable value in place, to pass it where a `PinStack` is expected.
+pub fn pin_unpin<T: Unpin>(value: &mut T) -> PinStack<'_, T> {
+    // Moving an `Unpin` value out of the pin is allowed anyway.
+    unsafe { pin_ptr(value) }
+}
+
+/// A transfer that can fail.
+///
+/// Types implementing `Transfer` implement `TryTransfer` with `Infallible` as the error type.
+///
+/// # Safety
+///
+/// * When `try_transfer` returns `Ok`, implementers are bound by the same rules as `Transfer`
+/// * When `try_transfer` returns `Err`, implementers **must** leave `src` untouched, and **must
+///   not** have written a value to `dst`
+/// * Implementers are **not** allowed to panic in the `try_transfer` function
+pub unsafe trait TryTransfer {
+    type Error;
+
+    /// # Safety
+    ///
+    /// Same as `Transfer::transfer`.
+    unsafe fn try_transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) -> Result<(), Self::Error>
+    where
+        Self: Sized;
+
+    /// Same as `Transfer::is_reset`, only checked after a successful transfer.
+    fn is_reset(&self) -> bool {
+        true
+    }
+
+    /// Same as `Transfer::NO_PADDING`.
+    const NO_PADDING: bool = false;
 }
 
-pub struct Tr<T: ?Sized>(T);
+unsafe impl<T: Transfer> TryTransfer for T {
+    type Error = Infallible;
 
-impl<T: Transfer> Tr<T> {
-    pub fn from_empty(empty: T) -> Self {
-        Self(empty)
+    unsafe fn try_transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) -> Result<(), Infallible> {
+        T::transfer(src, dst);
+        Ok(())
     }
 
-    fn slot(&mut self) -> *mut T {
-        &mut self.0 as *mut T
+    fn is_reset(&self) -> bool {
+        <T as Transfer>::is_reset(self)
     }
+
+    const NO_PADDING: bool = <T as Transfer>::NO_PADDING;
+}
+
+/// # Safety
+///
+/// `ptr` must point to a valid `T`, that stays pinned and valid for `'a`.
+pub(crate) unsafe fn pin_ptr<'a, T>(ptr: *mut T) -> PinStack<'a, T> {
+    Pin::new_unchecked(StackPinned::new(&mut *ptr))
 }
 
 pub fn transfer<'old, 'new, T>(
     mut src: PinStack<'old, T>,
-    dest: &'new mut Tr<T>,
+    dest: Pin<&'new mut Tr<T>>,
 ) -> PinStack<'new, T>
 where
     T: Transfer,
 {
-    use stackpin::StackPinned;
-    use std::pin::Pin;
     unsafe {
+        let dest = dest.get_unchecked_mut();
+        let slot = dest.slot();
+        guard::transfer(&mut src, slot);
+        dest.init = true;
+        pin_ptr(slot)
+    }
+}
+
+/// Transfers `src` to a new heap allocation.
+///
+/// Unlike a slot, the box can be leaked, so that the value is never dropped. `T` must be
+/// `'static`, so that values whose drop ends a borrow, such as a
+/// [`Lifetime`](dynref::Lifetime), cannot be boxed:
+///
+/// ```compile_fail,E0597
+/// use stackpin::stack_let;
+/// use transfer::dynref::DynRef;
+///
+/// let dr = DynRef::new();
+/// {
+///     let s = String::from("foo");
+///     stack_let!(lifetime = dr.lock(&s));
+///     std::mem::forget(transfer::transfer_to_box(lifetime));
+/// }
+/// dr.map(|s| s.len());
+/// ```
+pub fn transfer_to_box<T>(mut src: PinStack<'_, T>) -> Pin<Box<T>>
+where
+    T: Transfer + 'static,
+{
+    let slot = Box::into_raw(Box::new(MaybeUninit::<T>::uninit())) as *mut T;
+    unsafe {
+        guard::transfer(&mut src, slot);
+        Pin::new_unchecked(Box::from_raw(slot))
+    }
+}
+
+/// Transfers the contents of `src` to `dest`, then drops the reset source and frees its allocation.
+pub fn transfer_from_box<'new, T>(src: Pin<Box<T>>, dest: Pin<&'new mut Tr<T>>) -> PinStack<'new, T>
+where
+    T: Transfer,
+{
+    unsafe {
+        let raw = Box::into_raw(Pin::into_inner_unchecked(src));
+        let transferred = transfer(pin_ptr(raw), dest);
+        drop(Box::from_raw(raw));
+        transferred
+    }
+}
+
+/// Attempts to transfer `src` to `dest`.
+///
+/// On failure, the untouched source is returned along with the error.
+/// Any value previously transferred to `dest` is dropped, even on failure.
+pub fn try_transfer<'old, 'new, T>(
+    mut src: PinStack<'old, T>,
+    dest: Pin<&'new mut Tr<T>>,
+) -> Result<PinStack<'new, T>, (PinStack<'old, T>, T::Error)>
+where
+    T: TryTransfer,
+{
+    unsafe {
+        let dest = dest.get_unchecked_mut();
         let slot = dest.slot();
-        T::transfer(&mut src, slot);
-        Pin::new_unchecked(StackPinned::new(&mut *slot))
+        match guard::try_transfer(&mut src, slot) {
+            Ok(()) => {
+                dest.init = true;
+                Ok(pin_ptr(slot))
+            }
+            Err(err) => Err((src, err)),
+        }
+    }
+}
+
+/// Transfers `src` over the pinned value at `dst`, that is dropped first.
+///
+/// The caller still owns `dst`, so the process aborts if dropping it panics, rather than letting
+/// the caller drop it again.
+///
+/// # Safety
+///
+/// `dst` must hold a reset value, that is never used again.
+unsafe fn transfer_over<T: Transfer>(src: &mut PinStack<'_, T>, dst: &mut PinStack<'_, T>) {
+    let dst = dst.as_mut().get_unchecked_mut() as *mut T;
+    guard::drop_reset(dst);
+    guard::transfer(src, dst);
+}
+
+/// Exchanges the pinned values `a` and `b`.
+///
+/// `a` is transferred to a temporary slot, `b` to `a`, then the temporary to `b`, so that the
+/// values are fixed up for both sides. The reset values left by each transfer are dropped.
+pub fn swap<T: Transfer>(a: &mut PinStack<'_, T>, b: &mut PinStack<'_, T>) {
+    crate::slot!(tmp);
+    unsafe {
+        let mut tmp = transfer(pin_ptr(a.as_mut().get_unchecked_mut()), tmp);
+        transfer_over(b, a);
+        transfer_over(&mut tmp, b);
+    }
+}
+
+/// Replaces the pinned value `dst` with `src`, transferring the previous value of `dst` to `old`.
+///
+/// Any value previously transferred to `old` is dropped.
+pub fn replace<'old, T: Transfer>(
+    dst: &mut PinStack<'_, T>,
+    mut src: PinStack<'_, T>,
+    old: Pin<&'old mut Tr<T>>,
+) -> PinStack<'old, T> {
+    unsafe {
+        let previous = transfer(pin_ptr(dst.as_mut().get_unchecked_mut()), old);
+        transfer_over(&mut src, dst);
+        previous
     }
 }
 
+/// Declares an empty slot in the current stack frame, as a `Pin<&mut Tr<T>>`.
+///
+/// Like `stackpin::stack_let!`, the slot is shadowed by the pinned reference, so that it can be
+/// neither moved nor forgotten. The slot can be reused with `Pin::as_mut`:
+/// `slot!(mut id)` or `slot!(mut id: Type)` declare a mutable binding.
+#[macro_export]
+macro_rules! slot {
+    ($id:ident $(: $ty:ty)?) => {
+        let mut $id $(: $crate::Tr<$ty>)? = unsafe { $crate::Tr::uninit() };
+        let $id = unsafe { ::core::pin::Pin::new_unchecked(&mut $id) };
+    };
+    (mut $id:ident $(: $ty:ty)?) => {
+        let mut $id $(: $crate::Tr<$ty>)? = unsafe { $crate::Tr::uninit() };
+        let mut $id = unsafe { ::core::pin::Pin::new_unchecked(&mut $id) };
+    };
+}
+
+/// Declares a pinned value in the current stack frame, from another frame.
+///
+/// * `transfer_let!(id = expr)` transfers the `PinStack` returned by `expr` to the current frame.
+/// * `transfer_let!(id = f(args))`, where the expression ends with a call, passes a slot in the
+///   current frame as an additional last argument to the call, that must return a `PinStack` to
+///   that slot. The callee can be a function, a path, a method call or a turbofish.
+///
+/// To transfer the `PinStack` returned by a call rather than passing a slot to it, wrap the call in
+/// a block: `transfer_let!(id = { make_pinned() })`.
+///
+/// Like `let`, the binding can be `mut` and annotated with the pinned type:
+/// `transfer_let!(mut id: Type = expr)`. Several values can be transferred at once with
+/// `transfer_let!((a, mut b: Type) = (expr_a, expr_b))`, in which case each expression must be
+/// a `PinStack`.
 #[macro_export]
 macro_rules! transfer_let {
-    ($id:ident = $fun_name:ident ($($arg:expr),*)) => {
-        let mut $id = $crate::Transfer::empty();
-        let $id = $fun_name($($arg),* &mut $id);
+    (@munch [$($mut:tt)?] $id:ident [$($ty:ty)?] [$($callee:tt)+] ($($args:tt)*)) => {
+        $crate::slot!($id $(: $ty)?);
+        let $($mut)? $id $(: $crate::__private::PinStack<'_, $ty>)? =
+            $crate::__private::IntoPinStack::into_pin_stack(
+                $crate::transfer_let!(@call [$($callee)+] $id; $($args)*)
+            );
+    };
+    (@munch [$($mut:tt)?] $id:ident [$($ty:ty)?] [$($e:tt)*] $next:tt $($rest:tt)+) => {
+        $crate::transfer_let!(@munch [$($mut)?] $id [$($ty)?] [$($e)* $next] $($rest)+)
+    };
+    (@munch [$($mut:tt)?] $id:ident [$($ty:ty)?] [$($e:tt)*] $last:tt) => {
+        $crate::slot!($id $(: $ty)?);
+        let $($mut)? $id $(: $crate::__private::PinStack<'_, $ty>)? = $crate::transfer(
+            $crate::__private::IntoPinStack::into_pin_stack($($e)* $last),
+            $id,
+        );
+    };
+    (@call [$($callee:tt)+] $slot:expr; $($arg:expr),* $(,)?) => {
+        $($callee)+($($arg,)* $slot)
+    };
+    (@tuple [mut $id:ident $(: $ty:ty)? $(, $($rest:tt)*)?] [$e:expr $(, $es:expr)*]) => {
+        $crate::transfer_let!(mut $id $(: $ty)? = { $e });
+        $crate::transfer_let!(@tuple [$($($rest)*)?] [$($es),*]);
+    };
+    (@tuple [$id:ident $(: $ty:ty)? $(, $($rest:tt)*)?] [$e:expr $(, $es:expr)*]) => {
+        $crate::transfer_let!($id $(: $ty)? = { $e });
+        $crate::transfer_let!(@tuple [$($($rest)*)?] [$($es),*]);
+    };
+    (@tuple [] []) => {};
+    (@tuple [$($bindings:tt)*] [$($es:expr),*]) => {
+        compile_error!("`transfer_let!` expects as many values as bindings");
+    };
+    (($($bindings:tt)*) = ($($e:expr),+ $(,)?)) => {
+        $crate::transfer_let!(@tuple [$($bindings)*] [$($e),+]);
     };
+    (mut $id:ident $(: $ty:ty)? = $($e:tt)+) => {
+        $crate::transfer_let!(@munch [mut] $id [$($ty)?] [] $($e)+)
+    };
+    ($id:ident $(: $ty:ty)? = $($e:tt)+) => {
+        $crate::transfer_let!(@munch [] $id [$($ty)?] [] $($e)+)
+    };
+}
+
+#[macro_export]
+macro_rules! try_transfer_let {
     ($id:ident = $e:expr) => {
-        let mut $id = $crate::Transfer::empty();
-        let $id = $crate::transfer($e, &mut $id);
+        $crate::slot!($id);
+        let $id = $crate::try_transfer($e, $id);
     };
 }
 
+/// Support code for `#[derive(Transfer)]`, not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use stackpin::PinStack;
+
+    /// Checks that the right-hand side of `transfer_let!` is a `PinStack`.
+    #[diagnostic::on_unimplemented(
+        message = "`transfer_let!` expects a `PinStack<'_, T>`, found `{Self}`",
+        label = "not a pinned value",
+        note = "pin the value with `stackpin::stack_let!` first, or call a function taking a slot as its last argument"
+    )]
+    pub trait IntoPinStack<'a> {
+        type Target;
+
+        fn into_pin_stack(self) -> PinStack<'a, Self::Target>;
+    }
+
+    impl<'a, T> IntoPinStack<'a> for PinStack<'a, T> {
+        type Target = T;
+
+        fn into_pin_stack(self) -> Self {
+            self
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `field` must be a field of a pinned value.
+    pub unsafe fn pin_field<T>(field: &mut T) -> PinStack<'_, T> {
+        super::pin_ptr(field)
+    }
+
+    pub fn move_field<T: Unpin>(field: &mut T, reset: T) -> T {
+        std::mem::replace(field, reset)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     mod secret {
         use std::marker::PhantomPinned;
+        use std::pin::Pin;
         pub struct SecretU64(u64, PhantomPinned);
 
         fn secure_erase(x: &mut u64) {
             *x = 0;
         }
 
-        use super::super::{Tr, Transfer};
+        use super::super::Transfer;
         use stackpin::FromUnpinned;
         use stackpin::PinStack;
 
@@ -101,10 +506,6 @@ mod tests {
                     &mut src.as_mut().get_unchecked_mut().0
                 );
             }
-
-            fn empty() -> Tr<Self> {
-                Tr::from_empty(Self(0, PhantomPinned))
-            }
         }
 
         impl SecretU64 {
@@ -124,11 +525,57 @@ mod tests {
             }
         }
 
-        pub fn generate_secret(slot: &mut crate::Tr<SecretU64>) -> PinStack<'_, SecretU64> {
+        pub fn generate_secret(slot: Pin<&mut crate::Tr<SecretU64>>) -> PinStack<'_, SecretU64> {
             let mut secret = 42;
             stackpin::stack_let!(secret = stackpin::Unpinned::new(&mut secret));
             crate::transfer(secret, slot)
         }
+
+        pub fn generate_secret_from(
+            mut secret: u64,
+            slot: Pin<&mut crate::Tr<SecretU64>>,
+        ) -> PinStack<'_, SecretU64> {
+            stackpin::stack_let!(secret = stackpin::Unpinned::new(&mut secret));
+            crate::transfer(secret, slot)
+        }
+
+        pub fn generate_secret_as<S: Into<u64>>(
+            secret: S,
+            slot: Pin<&mut crate::Tr<SecretU64>>,
+        ) -> PinStack<'_, SecretU64> {
+            generate_secret_from(secret.into(), slot)
+        }
+
+        mod conformance {
+            use super::SecretU64;
+            use crate::testing::{Counted, DropToken};
+            use crate::Tr;
+            use stackpin::PinStack;
+            use std::pin::Pin;
+
+            type Checked = Counted<SecretU64>;
+
+            fn generate(slot: Pin<&mut Tr<Checked>>, token: DropToken) -> PinStack<'_, Checked> {
+                slot.emplace((&mut 42, token))
+            }
+
+            crate::check_transfer!(Checked, generate, |secret: &Checked| secret.0);
+        }
+
+        pub struct Generator {
+            pub seed: u64,
+        }
+
+        impl Generator {
+            pub fn generate<'a>(
+                &self,
+                offset: u64,
+                factor: u64,
+                slot: Pin<&'a mut crate::Tr<SecretU64>>,
+            ) -> PinStack<'a, SecretU64> {
+                generate_secret_from((self.seed + offset) * factor, slot)
+            }
+        }
     }
 
     use secret::SecretU64;
@@ -140,6 +587,116 @@ mod tests {
         assert_eq!(SecretU64::reveal(&my_secret), 42);
     }
 
+    mod call_forms {
+        use super::secret::{self, Generator, SecretU64};
+
+        #[test]
+        fn function_with_arguments() {
+            let base = 10;
+            crate::transfer_let!(my_secret = secret::generate_secret_from(base + 1));
+            assert_eq!(SecretU64::reveal(&my_secret), 11);
+        }
+
+        #[test]
+        fn method_call() {
+            let generator = Generator { seed: 3 };
+            crate::transfer_let!(my_secret = generator.generate(4, 2,));
+            assert_eq!(SecretU64::reveal(&my_secret), 14);
+        }
+
+        #[test]
+        fn path_and_turbofish() {
+            crate::transfer_let!(my_secret = self::secret::generate_secret());
+            assert_eq!(SecretU64::reveal(&my_secret), 42);
+            crate::transfer_let!(my_secret = secret::generate_secret_as::<u8>(7));
+            assert_eq!(SecretU64::reveal(&my_secret), 7);
+        }
+
+        #[test]
+        fn annotated_call() {
+            crate::transfer_let!(my_secret: SecretU64 = secret::generate_secret_from(9));
+            assert_eq!(SecretU64::reveal(&my_secret), 9);
+        }
+
+        #[test]
+        fn block_is_transferred() {
+            let mut initial_secret = 5u64;
+            stackpin::stack_let!(outer: SecretU64 = &mut initial_secret);
+            crate::transfer_let!(my_secret = { outer });
+            assert_eq!(SecretU64::reveal(&my_secret), 5);
+        }
+    }
+
+    mod returns_pinned {
+        use super::secret::SecretU64;
+        use stackpin::stack_let;
+        use transfer_derive::returns_pinned;
+
+        #[returns_pinned]
+        fn generate_secret(value: u64) -> SecretU64 {
+            if value == 0 {
+                let mut fallback = 1;
+                stack_let!(fallback: SecretU64 = &mut fallback);
+                return fallback;
+            }
+            #[allow(clippy::needless_return)]
+            let double = |value: u64| -> u64 { return value * 2 };
+            let mut secret = double(value);
+            stack_let!(secret: SecretU64 = &mut secret);
+            secret
+        }
+
+        struct Generator(u64);
+
+        impl Generator {
+            #[returns_pinned]
+            fn generate(&self, factor: u64) -> SecretU64 {
+                let mut secret = self.0 * factor;
+                stack_let!(secret: SecretU64 = &mut secret);
+                secret
+            }
+        }
+
+        #[test]
+        fn function() {
+            crate::transfer_let!(my_secret = generate_secret(6));
+            assert_eq!(SecretU64::reveal(&my_secret), 12);
+            crate::transfer_let!(my_secret = generate_secret(0));
+            assert_eq!(SecretU64::reveal(&my_secret), 1);
+        }
+
+        #[test]
+        fn method() {
+            let generator = Generator(5);
+            crate::transfer_let!(my_secret = generator.generate(3));
+            assert_eq!(SecretU64::reveal(&my_secret), 15);
+        }
+    }
+
+    mod bindings {
+        use super::secret::SecretU64;
+
+        #[test]
+        fn mut_and_annotated() {
+            let mut initial_secret = 3u64;
+            stackpin::stack_let!(outer: SecretU64 = &mut initial_secret);
+            crate::transfer_let!(mut my_secret: SecretU64 = outer);
+            let _: &mut stackpin::PinStack<'_, SecretU64> = &mut my_secret;
+            assert_eq!(SecretU64::reveal(&my_secret), 3);
+        }
+
+        #[test]
+        fn multiple() {
+            let (mut first, mut second) = (1u64, 2u64);
+            stackpin::stack_let!(outer_first: SecretU64 = &mut first);
+            stackpin::stack_let!(outer_second: SecretU64 = &mut second);
+            crate::transfer_let!((a, mut b: SecretU64) = (outer_first, outer_second));
+            let _: &mut stackpin::PinStack<'_, SecretU64> = &mut b;
+            assert_eq!(SecretU64::reveal(&a), 1);
+            assert_eq!(SecretU64::reveal(&b), 2);
+        }
+    }
+
     fn transfer_secret(outer_secret: stackpin::PinStack<'_, secret::SecretU64>) {
         super::transfer_let!(inner_secret = outer_secret);
         assert_eq!(SecretU64::reveal(&inner_secret), 83);
@@ -152,4 +709,403 @@ mod tests {
         transfer_secret(my_secret);
         assert_eq!(initial_secret, 0);
     }
+
+    #[test]
+    fn box_roundtrip() {
+        let mut initial_secret = 27u64;
+        stackpin::stack_let!(my_secret: SecretU64 = &mut initial_secret);
+        let boxed = crate::transfer_to_box(my_secret);
+        assert_eq!(initial_secret, 0);
+        crate::slot!(slot);
+        let unboxed = crate::transfer_from_box(boxed, slot);
+        assert_eq!(SecretU64::reveal(&unboxed), 27);
+    }
+
+    mod slot {
+        use crate::fixtures::pin_stack;
+        use crate::{transfer, Transfer};
+        use stackpin::{FromUnpinned, PinStack};
+        use std::cell::Cell;
+        use std::marker::PhantomPinned;
+        use std::pin::pin;
+        use std::ptr;
+
+        struct Counted<'a>(Option<&'a Cell<usize>>, PhantomPinned);
+
+        unsafe impl Transfer for Counted<'_> {
+            unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+                let counter = src.as_mut().get_unchecked_mut().0.take();
+                ptr::write(dst, Self(counter, PhantomPinned));
+            }
+        }
+
+        unsafe impl<'a> FromUnpinned<Option<&'a Cell<usize>>> for Counted<'a> {
+            type PinData = ();
+
+            unsafe fn from_unpinned(counter: Option<&'a Cell<usize>>) -> (Self, ()) {
+                (Self(counter, PhantomPinned), ())
+            }
+
+            unsafe fn on_pin(&mut self, _: ()) {}
+        }
+
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                if let Some(counter) = self.0 {
+                    counter.set(counter.get() + 1)
+                }
+            }
+        }
+
+        #[test]
+        fn uninit_slot_drops_nothing() {
+            let drops = Cell::new(0);
+            {
+                crate::slot!(_slot: Counted<'_>);
+            }
+            assert_eq!(drops.get(), 0);
+        }
+
+        /// Records the address at which it was pinned.
+        struct Placed<'a>(&'a Cell<usize>, PhantomPinned);
+
+        unsafe impl<'a> FromUnpinned<&'a Cell<usize>> for Placed<'a> {
+            type PinData = ();
+
+            unsafe fn from_unpinned(address: &'a Cell<usize>) -> (Self, ()) {
+                (Self(address, PhantomPinned), ())
+            }
+
+            unsafe fn on_pin(&mut self, _: ()) {
+                self.0.set(self as *mut Self as usize)
+            }
+        }
+
+        #[test]
+        fn emplace_pins_in_slot() {
+            let address = Cell::new(0);
+            crate::slot!(slot);
+            let placed: PinStack<'_, Placed<'_>> = slot.emplace(&address);
+            assert_eq!(address.get(), &*placed as *const Placed<'_> as usize);
+        }
+
+        #[test]
+        fn emplace_drops_previous_value() {
+            let drops = Cell::new(0);
+            let mut first = pin!(Counted(Some(&drops), PhantomPinned));
+            {
+                crate::slot!(mut slot);
+                transfer(pin_stack(&mut first), slot.as_mut());
+                slot.emplace(Some(&drops));
+                assert_eq!(drops.get(), 1);
+            }
+            assert_eq!(drops.get(), 2);
+        }
+
+        #[test]
+        fn slot_drops_transferred_values() {
+            let drops = Cell::new(0);
+            let mut first = pin!(Counted(Some(&drops), PhantomPinned));
+            let mut second = pin!(Counted(Some(&drops), PhantomPinned));
+            {
+                crate::slot!(mut slot);
+                transfer(pin_stack(&mut first), slot.as_mut());
+                assert_eq!(drops.get(), 0);
+                transfer(pin_stack(&mut second), slot.as_mut());
+                assert_eq!(drops.get(), 1);
+            }
+            assert_eq!(drops.get(), 2);
+        }
+    }
+
+    mod trivial {
+        use crate::vec::PinVec;
+        use crate::{pin_unpin, transfer, TriviallyTransferable};
+
+        #[test]
+        fn transfer_moves_and_resets() {
+            let mut name = String::from("alice");
+            crate::slot!(slot);
+            let moved = transfer(pin_unpin(&mut name), slot);
+            assert_eq!(*moved, "alice");
+            assert_eq!(name, "");
+        }
+
+        #[derive(Default, Debug, PartialEq)]
+        struct Point(i32, i32);
+
+        impl TriviallyTransferable for Point {}
+
+        #[test]
+        fn vec_of_movable_values() {
+            let mut vec = PinVec::new();
+            for i in 0..10 {
+                vec.push(pin_unpin(&mut Point(i, -i)));
+            }
+            crate::slot!(slot);
+            assert_eq!(*vec.remove(3, slot), Point(3, -3));
+            let xs: Vec<_> = vec.iter().map(|point| point.0).collect();
+            assert_eq!(xs, [0, 1, 2, 4, 5, 6, 7, 8, 9]);
+        }
+    }
+
+    mod swap {
+        use super::secret::SecretU64;
+        use crate::intrusive::{Link, List};
+        use crate::{replace, swap};
+        use stackpin::stack_let;
+
+        #[test]
+        fn swap_secrets() {
+            let (mut first, mut second) = (1u64, 2u64);
+            stack_let!(a: SecretU64 = &mut first);
+            stack_let!(b: SecretU64 = &mut second);
+            let (mut a, mut b) = (a, b);
+            swap(&mut a, &mut b);
+            assert_eq!(SecretU64::reveal(&a), 2);
+            assert_eq!(SecretU64::reveal(&b), 1);
+        }
+
+        #[test]
+        fn swap_fixes_up_both_sides() {
+            stack_let!(list: List<u32> = ());
+            stack_let!(linked: Link<u32> = 1);
+            stack_let!(unlinked: Link<u32> = 2);
+            list.as_ref().push_back(linked.as_ref());
+            let (mut linked, mut unlinked) = (linked, unlinked);
+            swap(&mut linked, &mut unlinked);
+            assert!(!linked.is_linked());
+            assert_eq!(*linked.get(), 2);
+            assert!(unlinked.is_linked());
+            let mut values = Vec::new();
+            list.as_ref().for_each(|value| values.push(*value));
+            assert_eq!(values, [1]);
+        }
+
+        #[test]
+        fn replace_secret() {
+            let (mut first, mut second) = (1u64, 2u64);
+            stack_let!(a: SecretU64 = &mut first);
+            stack_let!(b: SecretU64 = &mut second);
+            let mut a = a;
+            crate::slot!(old);
+            let old = replace(&mut a, b, old);
+            assert_eq!(SecretU64::reveal(&a), 2);
+            assert_eq!(SecretU64::reveal(&old), 1);
+        }
+    }
+
+    mod fallible {
+        use crate::fixtures::pin_stack;
+        use crate::TryTransfer;
+        use stackpin::PinStack;
+        use std::marker::PhantomPinned;
+        use std::pin::pin;
+        use std::ptr;
+
+        struct Ticket {
+            id: u32,
+            frozen: bool,
+            _pin: PhantomPinned,
+        }
+
+        #[derive(Debug, PartialEq)]
+        struct Frozen;
+
+        unsafe impl TryTransfer for Ticket {
+            type Error = Frozen;
+
+            unsafe fn try_transfer(
+                src: &mut PinStack<'_, Self>,
+                dst: *mut Self,
+            ) -> Result<(), Frozen> {
+                if src.frozen {
+                    return Err(Frozen);
+                }
+                let id = std::mem::replace(&mut src.as_mut().get_unchecked_mut().id, 0);
+                ptr::write(
+                    dst,
+                    Self {
+                        id,
+                        frozen: false,
+                        _pin: PhantomPinned,
+                    },
+                );
+                Ok(())
+            }
+        }
+
+        fn ticket(id: u32, frozen: bool) -> Ticket {
+            Ticket {
+                id,
+                frozen,
+                _pin: PhantomPinned,
+            }
+        }
+
+        #[test]
+        fn try_transfer_succeeds() {
+            let mut value = pin!(ticket(4, false));
+            crate::try_transfer_let!(moved = pin_stack(&mut value));
+            assert_eq!(moved.ok().map(|moved| moved.id), Some(4));
+            assert_eq!(value.id, 0);
+        }
+
+        #[test]
+        fn try_transfer_returns_source() {
+            let mut value = pin!(ticket(4, true));
+            crate::try_transfer_let!(moved = pin_stack(&mut value));
+            let (source, err) = moved.err().unwrap();
+            assert_eq!(err, Frozen);
+            assert_eq!(source.id, 4);
+        }
+    }
+
+    mod derive {
+        use super::secret::SecretU64;
+        use crate::__private::pin_field;
+        use crate::fixtures::{pin_stack, Tracked};
+        use crate::secret::Secret;
+        use crate::Transfer;
+        use stackpin::{FromUnpinned, PinStack};
+        use std::marker::PhantomPinned;
+        use std::pin::pin;
+
+        #[derive(transfer_derive::Transfer)]
+        struct Account {
+            secret: SecretU64,
+            #[transfer(move)]
+            id: u32,
+            #[transfer(reset = String::from("<moved>"))]
+            name: String,
+        }
+
+        unsafe impl<'a> FromUnpinned<&'a mut u64> for Account {
+            type PinData = &'a mut u64;
+
+            unsafe fn from_unpinned(src: &'a mut u64) -> (Self, &'a mut u64) {
+                let (secret, src) = SecretU64::from_unpinned(src);
+                let account = Self {
+                    secret,
+                    id: 7,
+                    name: String::from("alice"),
+                };
+                (account, src)
+            }
+
+            unsafe fn on_pin(&mut self, data: &'a mut u64) {
+                FromUnpinned::<&'a mut u64>::on_pin(&mut self.secret, data)
+            }
+        }
+
+        fn check_account(account: PinStack<'_, Account>) {
+            crate::transfer_let!(mut moved = account);
+            assert_eq!(moved.id, 7);
+            assert_eq!(moved.name, "alice");
+            let secret = unsafe { pin_field(&mut moved.as_mut().get_unchecked_mut().secret) };
+            assert_eq!(SecretU64::reveal(&secret), 12);
+        }
+
+        #[test]
+        fn derive_struct() {
+            let mut initial_secret = 12u64;
+            stackpin::stack_let!(account: Account = &mut initial_secret);
+            check_account(account);
+            assert_eq!(initial_secret, 0);
+        }
+
+        #[derive(transfer_derive::Transfer)]
+        enum Maybe<T> {
+            Nothing,
+            Just(T, #[transfer(move)] u32),
+        }
+
+        #[test]
+        fn derive_enum() {
+            let mut value = pin!(Maybe::Just(PhantomPinned, 3));
+            {
+                let pinned = pin_stack(&mut value);
+                crate::transfer_let!(moved = pinned);
+                assert!(matches!(*moved, Maybe::Just(_, 3)));
+            }
+            assert!(matches!(*value, Maybe::Just(_, 0)));
+
+            let mut value = pin!(Maybe::<PhantomPinned>::Nothing);
+            let pinned = pin_stack(&mut value);
+            crate::transfer_let!(moved = pinned);
+            assert!(matches!(*moved, Maybe::Nothing));
+        }
+
+        fn src() -> String {
+            String::from("<moved>")
+        }
+
+        /// The reset expressions cannot see the bindings of the derived implementation.
+        #[derive(transfer_derive::Transfer)]
+        struct Named {
+            #[transfer(reset = src())]
+            name: String,
+        }
+
+        #[derive(transfer_derive::Transfer)]
+        struct Holder {
+            value: Option<Tracked>,
+            #[transfer(move)]
+            id: u32,
+        }
+
+        #[test]
+        fn derived_is_reset() {
+            let mut holder = pin!(Holder {
+                value: Some(Tracked::new(1)),
+                id: 2,
+            });
+            assert!(!holder.is_reset());
+            {
+                let pinned = pin_stack(&mut holder);
+                crate::transfer_let!(moved = pinned);
+                assert!(!moved.is_reset());
+            }
+            assert!(holder.is_reset());
+
+            let mut value = pin!(Maybe::Just(Some(Tracked::new(1)), 3));
+            assert!(!value.is_reset());
+            {
+                let pinned = pin_stack(&mut value);
+                crate::transfer_let!(_moved = pinned);
+            }
+            assert!(value.is_reset());
+            assert!(Maybe::<Option<Tracked>>::Nothing.is_reset());
+        }
+
+        #[derive(transfer_derive::Transfer)]
+        struct Secrets(Secret<u64>, Secret<u32>, Secret<u32>);
+
+        #[derive(transfer_derive::Transfer)]
+        struct Padded(Secret<u64>, Secret<u8>);
+
+        #[test]
+        fn derived_no_padding() {
+            let no_padding = [
+                <Secrets as Transfer>::NO_PADDING,
+                <Padded as Transfer>::NO_PADDING,
+                <Account as Transfer>::NO_PADDING,
+                <Maybe<Secret<u64>> as Transfer>::NO_PADDING,
+            ];
+            assert_eq!(no_padding, [true, false, false, false]);
+        }
+
+        #[test]
+        fn reset_expression_hygiene() {
+            let mut value = pin!(Named {
+                name: String::from("bob"),
+            });
+            {
+                let pinned = pin_stack(&mut value);
+                crate::transfer_let!(moved = pinned);
+                assert_eq!(moved.name, "bob");
+            }
+            assert_eq!(value.name, "<moved>");
+        }
+    }
 }
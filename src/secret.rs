@@ -0,0 +1,174 @@
+//! Secrets that are erased from memory on construction, on transfer, and on drop.
+//!
+//! A [`Secret`] is constructed from a mutable reference to its value with `stack_let!`, which
+//! erases the original value. Transferring the secret erases the source, so that no copy of the
+//! value survives in memory.
+//!
+//! With the `mlock` feature on Linux, [`LockedSecret`] additionally locks the memory pages of the
+//! secret, so that they are never swapped to disk.
+
+#[cfg(all(feature = "mlock", target_os = "linux"))]
+mod locked;
+
+#[cfg(all(feature = "mlock", target_os = "linux"))]
+pub use locked::LockedSecret;
+
+use crate::Transfer;
+use stackpin::{FromUnpinned, PinStack};
+use std::marker::PhantomPinned;
+use std::mem;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::atomic::{compiler_fence, Ordering};
+
+/// Types for which a value with all bytes set to zero is valid.
+///
+/// # Safety
+///
+/// Implementers **must** accept the all-zero bit pattern as a valid value, **must not** hold
+/// resources that are leaked when overwritten, and **must not** have padding bytes, as all the
+/// bytes of the value are read to check that it was erased.
+pub unsafe trait Zeroable: Sized {}
+
+macro_rules! zeroable {
+    ($($t:ty),*) => {
+        $(unsafe impl Zeroable for $t {})*
+    };
+}
+
+zeroable!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+unsafe impl<T: Zeroable, const N: usize> Zeroable for [T; N] {}
+
+/// Overwrites `value` with zeroes, in a way that the compiler does not optimize out.
+pub fn erase<T: Zeroable>(value: &mut T) {
+    let bytes = value as *mut T as *mut u8;
+    for i in 0..mem::size_of::<T>() {
+        unsafe { ptr::write_volatile(bytes.add(i), 0) }
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+fn is_erased<T: Zeroable>(value: &T) -> bool {
+    let bytes = value as *const T as *const u8;
+    (0..mem::size_of::<T>()).all(|i| unsafe { *bytes.add(i) } == 0)
+}
+
+pub struct Secret<T: Zeroable> {
+    value: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Zeroable> Secret<T> {
+    fn zeroed() -> Self {
+        Self {
+            value: unsafe { mem::zeroed() },
+            _pin: PhantomPinned,
+        }
+    }
+
+    /// Calls `f` with a shared reference to the secret value.
+    pub fn reveal<U, F: FnOnce(&T) -> U>(&self, f: F) -> U {
+        f(&self.value)
+    }
+
+    /// Calls `f` with a mutable reference to the secret value.
+    pub fn expose<U, F: FnOnce(&mut T) -> U>(self: Pin<&mut Self>, f: F) -> U {
+        f(unsafe { &mut self.get_unchecked_mut().value })
+    }
+}
+
+unsafe impl<'a, T: Zeroable> FromUnpinned<&'a mut T> for Secret<T> {
+    type PinData = &'a mut T;
+
+    unsafe fn from_unpinned(src: &'a mut T) -> (Self, &'a mut T) {
+        (Self::zeroed(), src)
+    }
+
+    unsafe fn on_pin(&mut self, data: &'a mut T) {
+        ptr::copy_nonoverlapping(data as *const T, &mut self.value, 1);
+        erase(data);
+    }
+}
+
+unsafe impl<T: Zeroable> Transfer for Secret<T> {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let src = src.as_mut().get_unchecked_mut();
+        // Copy directly from the source to the destination, without going through the stack.
+        ptr::copy_nonoverlapping(src as *const Self, dst, 1);
+        erase(&mut src.value);
+    }
+
+    fn is_reset(&self) -> bool {
+        is_erased(&self.value)
+    }
+
+    // `Zeroable` types have no padding, and neither has `PhantomPinned`.
+    const NO_PADDING: bool = true;
+}
+
+impl<T: Zeroable> Drop for Secret<T> {
+    fn drop(&mut self) {
+        erase(&mut self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{erase, Secret};
+    use crate::fixtures::pin_stack;
+    use stackpin::{stack_let, PinStack};
+
+    mod conformance {
+        use super::Secret;
+        use crate::testing::{Counted, DropToken};
+        use crate::Tr;
+        use stackpin::PinStack;
+        use std::pin::Pin;
+
+        type Checked = Counted<Secret<u64>>;
+
+        fn generate(slot: Pin<&mut Tr<Checked>>, token: DropToken) -> PinStack<'_, Checked> {
+            slot.emplace((&mut 42, token))
+        }
+
+        crate::testing::check_transfer!(Checked, generate, |secret: &Checked| {
+            secret.reveal(|value| *value)
+        });
+    }
+
+    #[test]
+    fn erase_integers_and_arrays() {
+        let mut key = [0xabu8; 32];
+        erase(&mut key);
+        assert_eq!(key, [0; 32]);
+        let mut pin = 1234u32;
+        erase(&mut pin);
+        assert_eq!(pin, 0);
+    }
+
+    #[test]
+    fn construction_erases_source() {
+        let mut key = *b"hunter2!";
+        stack_let!(secret: Secret<[u8; 8]> = &mut key);
+        assert_eq!(key, [0; 8]);
+        assert_eq!(secret.reveal(|key| *key), *b"hunter2!");
+    }
+
+    fn check_transferred(secret: PinStack<'_, Secret<u64>>) {
+        crate::transfer_let!(mut moved = secret);
+        assert_eq!(moved.reveal(|value| *value), 42);
+        moved.as_mut().expose(|value| *value += 1);
+        assert_eq!(moved.reveal(|value| *value), 43);
+    }
+
+    #[test]
+    fn transfer_erases_source() {
+        let mut value = 42u64;
+        stack_let!(secret: Secret<u64> = &mut value);
+        let mut secret = secret;
+        assert_eq!(value, 0);
+        check_transferred(pin_stack(&mut secret));
+        assert_eq!(secret.reveal(|value| *value), 0);
+    }
+}
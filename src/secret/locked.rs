@@ -0,0 +1,211 @@
+//! Secrets whose memory pages are locked with `mlock`, so that they are never swapped to disk.
+
+use super::{erase, is_erased, Zeroable};
+use crate::Transfer;
+use stackpin::{FromUnpinned, PinStack};
+use std::collections::BTreeMap;
+use std::marker::PhantomPinned;
+use std::mem;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::Mutex;
+
+/// Locked pages, by address.
+///
+/// Several secrets can share a page: a page is locked when its first secret registers, and
+/// unlocked when its last secret unregisters.
+static PAGES: Mutex<BTreeMap<usize, Page>> = Mutex::new(BTreeMap::new());
+
+struct Page {
+    secrets: usize,
+    /// Whether `mlock` succeeded for this page.
+    locked: bool,
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Addresses of the pages that `size` bytes starting at `addr` span.
+fn pages(addr: usize, size: usize) -> impl Iterator<Item = usize> {
+    let page_size = page_size();
+    let first = addr & !(page_size - 1);
+    let end = if size == 0 { first } else { addr + size };
+    (first..end).step_by(page_size)
+}
+
+fn register(addr: usize, size: usize) {
+    let mut registry = PAGES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for page in pages(addr, size) {
+        let entry = registry.entry(page).or_insert_with(|| Page {
+            secrets: 0,
+            locked: unsafe { libc::mlock(page as *const libc::c_void, page_size()) == 0 },
+        });
+        entry.secrets += 1;
+    }
+}
+
+fn unregister(addr: usize, size: usize) {
+    let mut registry = PAGES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for page in pages(addr, size) {
+        if let Some(entry) = registry.get_mut(&page) {
+            entry.secrets -= 1;
+            if entry.secrets == 0 {
+                if entry.locked {
+                    unsafe { libc::munlock(page as *const libc::c_void, page_size()) };
+                }
+                registry.remove(&page);
+            }
+        }
+    }
+}
+
+fn is_locked(addr: usize, size: usize) -> bool {
+    let registry = PAGES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    pages(addr, size).all(|page| registry.get(&page).is_some_and(|entry| entry.locked))
+}
+
+/// A [`Secret`](super::Secret) whose pages are locked in memory for as long as it is pinned there.
+///
+/// * Pinning locks the pages of the secret, before the value is copied in
+/// * Transferring locks the pages of the destination before copying, and unlocks the pages of
+///   the source after erasing it
+/// * Dropping erases the value, then unlocks its pages
+///
+/// Locking is best-effort: when `mlock` fails, for instance because of `RLIMIT_MEMLOCK`, the
+/// secret is still erased but `is_locked` returns `false`.
+pub struct LockedSecret<T: Zeroable> {
+    value: T,
+    /// Whether the pages of this secret are registered.
+    registered: bool,
+    _pin: PhantomPinned,
+}
+
+impl<T: Zeroable> LockedSecret<T> {
+    fn zeroed() -> Self {
+        Self {
+            value: unsafe { mem::zeroed() },
+            registered: false,
+            _pin: PhantomPinned,
+        }
+    }
+
+    fn addr(&self) -> usize {
+        &self.value as *const T as usize
+    }
+
+    /// Calls `f` with a shared reference to the secret value.
+    pub fn reveal<U, F: FnOnce(&T) -> U>(&self, f: F) -> U {
+        f(&self.value)
+    }
+
+    /// Calls `f` with a mutable reference to the secret value.
+    pub fn expose<U, F: FnOnce(&mut T) -> U>(self: Pin<&mut Self>, f: F) -> U {
+        f(unsafe { &mut self.get_unchecked_mut().value })
+    }
+
+    /// Whether all the pages of the secret are currently locked in memory.
+    pub fn is_locked(&self) -> bool {
+        self.registered && is_locked(self.addr(), mem::size_of::<T>())
+    }
+}
+
+unsafe impl<'a, T: Zeroable> FromUnpinned<&'a mut T> for LockedSecret<T> {
+    type PinData = &'a mut T;
+
+    unsafe fn from_unpinned(src: &'a mut T) -> (Self, &'a mut T) {
+        (Self::zeroed(), src)
+    }
+
+    unsafe fn on_pin(&mut self, data: &'a mut T) {
+        register(self.addr(), mem::size_of::<T>());
+        self.registered = true;
+        ptr::copy_nonoverlapping(data as *const T, &mut self.value, 1);
+        erase(data);
+    }
+}
+
+unsafe impl<T: Zeroable> Transfer for LockedSecret<T> {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let src = src.as_mut().get_unchecked_mut();
+        let dst_value = ptr::addr_of_mut!((*dst).value);
+        register(dst_value as usize, mem::size_of::<T>());
+        ptr::copy_nonoverlapping(&src.value, dst_value, 1);
+        ptr::write(ptr::addr_of_mut!((*dst).registered), true);
+        erase(&mut src.value);
+        if src.registered {
+            unregister(src.addr(), mem::size_of::<T>());
+            src.registered = false;
+        }
+    }
+
+    fn is_reset(&self) -> bool {
+        !self.registered && is_erased(&self.value)
+    }
+}
+
+impl<T: Zeroable> Drop for LockedSecret<T> {
+    fn drop(&mut self) {
+        erase(&mut self.value);
+        if self.registered {
+            unregister(self.addr(), mem::size_of::<T>());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_locked, page_size, register, unregister, LockedSecret, PAGES};
+    use crate::fixtures::pin_stack;
+    use stackpin::{stack_let, PinStack};
+
+    fn secrets_on_page(addr: usize) -> usize {
+        let page = addr & !(page_size() - 1);
+        PAGES
+            .lock()
+            .unwrap()
+            .get(&page)
+            .map_or(0, |entry| entry.secrets)
+    }
+
+    #[test]
+    fn shared_pages_are_refcounted() {
+        let buffer = vec![0u8; 2 * page_size()];
+        let addr = buffer.as_ptr() as usize;
+        register(addr, 16);
+        register(addr + 16, 16);
+        assert_eq!(secrets_on_page(addr), 2);
+        unregister(addr, 16);
+        assert_eq!(secrets_on_page(addr), 1);
+        unregister(addr + 16, 16);
+        assert_eq!(secrets_on_page(addr), 0);
+        assert!(!is_locked(addr, 16));
+    }
+
+    #[test]
+    fn pinned_secret_is_registered() {
+        let mut key = [7u8; 16];
+        stack_let!(secret: LockedSecret<[u8; 16]> = &mut key);
+        assert_eq!(key, [0; 16]);
+        assert!(secrets_on_page(secret.addr()) >= 1);
+        assert_eq!(secret.reveal(|key| *key), [7; 16]);
+    }
+
+    fn check_transferred(secret: PinStack<'_, LockedSecret<u64>>) {
+        crate::transfer_let!(moved = secret);
+        assert_eq!(moved.reveal(|value| *value), 42);
+        assert!(secrets_on_page(moved.addr()) >= 1);
+    }
+
+    #[test]
+    fn transfer_moves_registration() {
+        let mut value = 42u64;
+        stack_let!(secret: LockedSecret<u64> = &mut value);
+        let mut secret = secret;
+        assert!(secret.registered);
+        check_transferred(pin_stack(&mut secret));
+        assert!(!secret.registered);
+        assert!(!secret.is_locked());
+        assert_eq!(secret.reveal(|value| *value), 0);
+    }
+}
@@ -0,0 +1,202 @@
+//! Pointers into the object that contains them, rebased on transfer.
+//!
+//! A [`SelfRef`] is a field that points to another part of the same object, such as an inline
+//! buffer. When the object is transferred, a field lands at the same offset in the destination as
+//! in the source, so the `SelfRef` rebases its pointer by the distance between its own source and
+//! destination addresses. Deriving `Transfer` on the containing struct is then enough to keep the
+//! pointer valid.
+//!
+//! The pointee must be part of the same object: a pointer to a heap allocation owned by the
+//! object does not move with it, and must not be stored in a `SelfRef`.
+//!
+//! Pointers to unsized targets, such as slices or trait objects, are rebased by replacing their
+//! data pointer, assumed to be their first word. Rust does not guarantee this layout, but stable
+//! Rust has no other way to build such a pointer from the destination; a transfer aborts if the
+//! assumption does not hold.
+
+use crate::Transfer;
+use stackpin::PinStack;
+use std::any;
+use std::ptr;
+
+/// A pointer to a part of the object containing this `SelfRef`.
+pub struct SelfRef<T: ?Sized> {
+    ptr: Option<*const T>,
+}
+
+impl<T: ?Sized> SelfRef<T> {
+    pub const fn new() -> Self {
+        Self { ptr: None }
+    }
+
+    /// Points to `target`, that should be part of the object containing this `SelfRef`.
+    pub fn set(&mut self, target: *const T) {
+        self.ptr = Some(target)
+    }
+
+    pub fn clear(&mut self) {
+        self.ptr = None
+    }
+
+    pub fn is_some(&self) -> bool {
+        self.ptr.is_some()
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.ptr.is_none()
+    }
+
+    pub fn as_ptr(&self) -> Option<*const T> {
+        self.ptr
+    }
+
+    /// Returns a reference to the target, if any.
+    ///
+    /// # Safety
+    ///
+    /// The target **must** be valid for reads, and must not be mutated for the lifetime of the
+    /// returned reference.
+    pub unsafe fn get(&self) -> Option<&T> {
+        self.ptr.map(|ptr| &*ptr)
+    }
+}
+
+impl<T: ?Sized> Default for SelfRef<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns `ptr`, with the metadata of `ptr` but the address and provenance of `data`.
+fn with_data<T: ?Sized>(mut ptr: *const T, data: *const u8) -> *const T {
+    // Assumes that the data pointer is the first word of pointers to unsized types as well.
+    unsafe { *(&mut ptr as *mut *const T as *mut *const u8) = data };
+    assert_eq!(
+        ptr as *const u8,
+        data,
+        "the data pointer is not the first word of `*const {}`",
+        any::type_name::<T>()
+    );
+    ptr
+}
+
+unsafe impl<T: ?Sized> Transfer for SelfRef<T> {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let src = src.as_mut().get_unchecked_mut();
+        // The rebased pointer is derived from `dst`, as a pointer derived from the source would not
+        // be allowed to access the destination object.
+        let ptr = src.ptr.take().map(|ptr| {
+            let offset = (ptr as *const u8 as isize).wrapping_sub(src as *mut Self as isize);
+            with_data(ptr, (dst as *const u8).wrapping_offset(offset))
+        });
+        ptr::write(dst, Self { ptr });
+    }
+
+    fn is_reset(&self) -> bool {
+        self.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SelfRef;
+    use crate::transfer;
+    use stackpin::{stack_let, FromUnpinned, PinStack};
+    use std::fmt::Debug;
+    use std::marker::PhantomPinned;
+
+    /// A buffer with a cursor pointing into it.
+    #[derive(transfer_derive::Transfer)]
+    struct Reader {
+        #[transfer(move)]
+        buf: [u8; 16],
+        cursor: SelfRef<u8>,
+        word: SelfRef<[u8]>,
+        shown: SelfRef<dyn Debug>,
+        _pin: PhantomPinned,
+    }
+
+    impl Reader {
+        fn current(&self) -> u8 {
+            unsafe { *self.cursor.get().unwrap() }
+        }
+
+        fn word(&self) -> &[u8] {
+            unsafe { self.word.get().unwrap() }
+        }
+
+        fn shown(&self) -> String {
+            format!("{:?}", unsafe { self.shown.get().unwrap() })
+        }
+    }
+
+    unsafe impl FromUnpinned<[u8; 16]> for Reader {
+        type PinData = ();
+
+        unsafe fn from_unpinned(buf: [u8; 16]) -> (Self, ()) {
+            let reader = Self {
+                buf,
+                cursor: SelfRef::new(),
+                word: SelfRef::new(),
+                shown: SelfRef::new(),
+                _pin: PhantomPinned,
+            };
+            (reader, ())
+        }
+
+        unsafe fn on_pin(&mut self, _: ()) {
+            self.cursor.set(&self.buf[6]);
+            self.word.set(&self.buf[..5]);
+            self.shown.set(&self.buf[12]);
+        }
+    }
+
+    mod conformance {
+        use super::Reader;
+        use crate::testing::{Counted, DropToken};
+        use crate::Tr;
+        use stackpin::PinStack;
+        use std::pin::Pin;
+
+        type Checked = Counted<Reader>;
+
+        fn generate(slot: Pin<&mut Tr<Checked>>, token: DropToken) -> PinStack<'_, Checked> {
+            slot.emplace((*b"hello world!!!!!", token))
+        }
+
+        crate::check_transfer!(Checked, generate, |reader: &Checked| {
+            (reader.current(), reader.word().to_vec(), reader.shown())
+        });
+    }
+
+    fn check_reader(reader: PinStack<'_, Reader>) {
+        assert_eq!(reader.current(), b'w');
+        assert_eq!(reader.word(), b"hello");
+        assert_eq!(reader.shown(), "33");
+    }
+
+    #[test]
+    fn transfer_rebases_pointers() {
+        crate::slot!(outer);
+        let reader = {
+            stack_let!(reader: Reader = *b"hello world!!!!!");
+            let src = &*reader as *const Reader;
+            let reader = transfer(reader, outer);
+            assert_ne!(src, &*reader as *const Reader);
+            reader
+        };
+        assert_eq!(reader.cursor.as_ptr().unwrap(), &reader.buf[6] as *const u8);
+        assert_eq!(reader.shown.as_ptr().unwrap() as *const u8, &reader.buf[12] as *const u8);
+        check_reader(reader);
+    }
+
+    #[test]
+    fn transfer_resets_source() {
+        let mut cursor = SelfRef::<u8>::new();
+        let target = 0u8;
+        cursor.set(&target);
+        crate::slot!(outer);
+        transfer(crate::pin_unpin(&mut cursor), outer);
+        assert!(cursor.is_none());
+    }
+}
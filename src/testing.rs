@@ -0,0 +1,414 @@
+//! Conformance checks for `Transfer` implementations.
+//!
+//! [`check_transfer!`](crate::check_transfer) generates a test for each check of this module,
+//! from a constructor building the value in a slot, and optionally a function observing the
+//! value, whose result must be the same before and after transfers.
+//!
+//! The constructor is given a [`DropToken`], that the value must hold and transfer along, so that
+//! the checks can count how many times the value is dropped. A type that cannot hold a token is
+//! checked wrapped in a [`Counted`]:
+//!
+//! ```ignore
+//! mod secret_conformance {
+//!     use super::*;
+//!     use transfer::testing::{Counted, DropToken};
+//!
+//!     type Checked = Counted<Secret<u64>>;
+//!
+//!     fn generate(slot: Pin<&mut Tr<Checked>>, token: DropToken) -> PinStack<'_, Checked> {
+//!         slot.emplace((&mut 42, token))
+//!     }
+//!
+//!     transfer::testing::check_transfer!(Checked, generate, |secret: &Checked| {
+//!         secret.reveal(|value| *value)
+//!     });
+//! }
+//! ```
+//!
+//! The sources of the transfers are checked with `Transfer::is_reset`. Addresses are compared with
+//! `ptr::eq`, so that the checks can run under Miri, which also detects double drops and leaks.
+
+use crate::{pin_ptr, transfer, Tr, Transfer, TriviallyTransferable};
+use stackpin::{FromUnpinned, PinStack};
+use std::cell::Cell;
+use std::fmt::Debug;
+use std::mem;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::ptr;
+use std::rc::Rc;
+
+#[doc(inline)]
+pub use crate::check_transfer;
+
+/// Builds a pinned value in a slot, holding the given token.
+pub type Constructor<T> = for<'a> fn(Pin<&'a mut Tr<T>>, DropToken) -> PinStack<'a, T>;
+
+/// Counts the drops of the values holding one of its tokens.
+#[derive(Default)]
+pub struct DropCounter(Rc<Cell<usize>>);
+
+impl DropCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a token counting a drop when the value holding it is dropped.
+    pub fn token(&self) -> DropToken {
+        DropToken(Some(self.0.clone()))
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.get()
+    }
+
+    fn assert_count(&self, expected: usize) {
+        assert_eq!(
+            self.count(),
+            expected,
+            "expected {} drops of the value, found {}",
+            expected,
+            self.count()
+        );
+    }
+}
+
+/// Counts a drop of the value holding it.
+///
+/// Transferring the token leaves an empty token in the source, that does not count its drop.
+#[derive(Default)]
+pub struct DropToken(Option<Rc<Cell<usize>>>);
+
+impl TriviallyTransferable for DropToken {}
+
+impl Drop for DropToken {
+    fn drop(&mut self) {
+        if let Some(count) = &self.0 {
+            count.set(count.get() + 1)
+        }
+    }
+}
+
+/// A value along with a [`DropToken`], to check types that cannot hold one.
+///
+/// `Counted<T>` is built from `(data, token)` when `T` is built from `data`.
+pub struct Counted<T> {
+    value: T,
+    token: DropToken,
+}
+
+impl<T> Deref for Counted<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+unsafe impl<S, T: FromUnpinned<S>> FromUnpinned<(S, DropToken)> for Counted<T> {
+    type PinData = T::PinData;
+
+    unsafe fn from_unpinned((data, token): (S, DropToken)) -> (Self, T::PinData) {
+        let (value, pin_data) = T::from_unpinned(data);
+        (Self { value, token }, pin_data)
+    }
+
+    unsafe fn on_pin(&mut self, pin_data: T::PinData) {
+        self.value.on_pin(pin_data)
+    }
+}
+
+unsafe impl<T: Transfer> Transfer for Counted<T> {
+    unsafe fn transfer(src: &mut PinStack<'_, Self>, dst: *mut Self) {
+        let src = src.as_mut().get_unchecked_mut();
+        T::transfer(
+            &mut pin_ptr(&mut src.value),
+            ptr::addr_of_mut!((*dst).value),
+        );
+        ptr::write(ptr::addr_of_mut!((*dst).token), mem::take(&mut src.token));
+    }
+
+    fn is_reset(&self) -> bool {
+        self.value.is_reset() && self.token.0.is_none()
+    }
+}
+
+fn assert_in_slot<T>(value: &PinStack<'_, T>, slot: *const T) {
+    assert!(
+        ptr::eq(&**value, slot),
+        "the value is not at the address of its slot"
+    );
+}
+
+/// Builds a value with `constructor`, that must build it in `slot`.
+fn construct<'a, T>(
+    constructor: Constructor<T>,
+    slot: Pin<&'a mut Tr<T>>,
+    drops: &DropCounter,
+) -> PinStack<'a, T> {
+    let ptr = slot.slot.as_ptr();
+    let value = constructor(slot, drops.token());
+    assert_in_slot(&value, ptr);
+    drops.assert_count(0);
+    value
+}
+
+fn assert_reset<T: Transfer>(slot: &Tr<T>) {
+    assert!(
+        slot.init,
+        "the constructor did not build the value in its slot"
+    );
+    assert!(
+        unsafe { (*slot.slot.as_ptr()).is_reset() },
+        "the source was not reset by the transfer"
+    );
+}
+
+fn assert_moved<T>(src: *const T, dst: &PinStack<'_, T>) {
+    assert!(
+        !ptr::eq(src, &**dst),
+        "the transferred value is at the address of the source"
+    );
+}
+
+/// Constructs a value in a callee frame, and transfers it to the slot of the caller.
+pub fn transfer_out<T: Transfer, O: PartialEq + Debug>(
+    constructor: Constructor<T>,
+    observe: fn(&T) -> O,
+) {
+    fn produce<'a, T: Transfer, O>(
+        constructor: Constructor<T>,
+        observe: fn(&T) -> O,
+        drops: &DropCounter,
+        slot: Pin<&'a mut Tr<T>>,
+    ) -> (PinStack<'a, T>, O) {
+        crate::slot!(mut inner);
+        let value = construct(constructor, inner.as_mut(), drops);
+        let expected = observe(&value);
+        let src: *const T = &*value;
+        let moved = transfer(value, slot);
+        assert_moved(src, &moved);
+        assert_reset(&inner);
+        (moved, expected)
+    }
+
+    let drops = DropCounter::new();
+    {
+        crate::slot!(outer);
+        let slot = outer.slot.as_ptr();
+        let (value, expected) = produce(constructor, observe, &drops, outer);
+        drops.assert_count(0);
+        assert_in_slot(&value, slot);
+        assert_eq!(observe(&value), expected);
+    }
+    drops.assert_count(1);
+}
+
+/// Constructs a value in the caller frame, and transfers it to the slot of a callee.
+pub fn transfer_in<T: Transfer, O: PartialEq + Debug>(
+    constructor: Constructor<T>,
+    observe: fn(&T) -> O,
+) {
+    fn consume<T: Transfer, O: PartialEq + Debug>(
+        value: PinStack<'_, T>,
+        observe: fn(&T) -> O,
+        expected: O,
+    ) {
+        let src: *const T = &*value;
+        crate::slot!(inner);
+        let slot = inner.slot.as_ptr();
+        let moved = transfer(value, inner);
+        assert_in_slot(&moved, slot);
+        assert_moved(src, &moved);
+        assert_eq!(observe(&moved), expected);
+    }
+
+    let drops = DropCounter::new();
+    {
+        crate::slot!(mut outer);
+        let value = construct(constructor, outer.as_mut(), &drops);
+        let expected = observe(&value);
+        consume(value, observe, expected);
+        drops.assert_count(1);
+        assert_reset(&outer);
+    }
+    drops.assert_count(1);
+}
+
+/// Transfers a value through several nested frames.
+pub fn chained_transfers<T: Transfer, O: PartialEq + Debug>(
+    constructor: Constructor<T>,
+    observe: fn(&T) -> O,
+) {
+    fn chain<T: Transfer, O: PartialEq + Debug>(
+        value: PinStack<'_, T>,
+        observe: fn(&T) -> O,
+        expected: &O,
+        depth: usize,
+    ) {
+        let src: *const T = &*value;
+        crate::slot!(slot);
+        let moved = transfer(value, slot);
+        assert_moved(src, &moved);
+        assert_eq!(&observe(&moved), expected);
+        if depth > 0 {
+            chain(moved, observe, expected, depth - 1);
+        }
+    }
+
+    let drops = DropCounter::new();
+    {
+        crate::slot!(outer);
+        let value = construct(constructor, outer, &drops);
+        let expected = observe(&value);
+        chain(value, observe, &expected, 8);
+        drops.assert_count(1);
+    }
+    drops.assert_count(1);
+}
+
+/// Drops the reset source before the destination, that must be unaffected.
+pub fn drop_source_first<T: Transfer, O: PartialEq + Debug>(
+    constructor: Constructor<T>,
+    observe: fn(&T) -> O,
+) {
+    let drops = DropCounter::new();
+    {
+        crate::slot!(dst);
+        let (value, expected) = {
+            crate::slot!(mut src);
+            let value = construct(constructor, src.as_mut(), &drops);
+            let expected = observe(&value);
+            let value = transfer(value, dst);
+            assert_reset(&src);
+            (value, expected)
+        };
+        drops.assert_count(0);
+        assert_eq!(observe(&value), expected);
+    }
+    drops.assert_count(1);
+}
+
+/// Transfers a value to a slot that already holds one, which drops the previous value.
+pub fn reuse_slot<T: Transfer, O: PartialEq + Debug>(
+    constructor: Constructor<T>,
+    observe: fn(&T) -> O,
+) {
+    let drops = DropCounter::new();
+    {
+        crate::slot!(mut dst);
+        crate::slot!(first);
+        transfer(construct(constructor, first, &drops), dst.as_mut());
+        crate::slot!(second);
+        let value = construct(constructor, second, &drops);
+        let expected = observe(&value);
+        let value = transfer(value, dst);
+        drops.assert_count(1);
+        assert_eq!(observe(&value), expected);
+    }
+    drops.assert_count(2);
+}
+
+/// Drops a slot no value was transferred to, which must not drop anything, next to a slot holding
+/// a value, that must be dropped once.
+pub fn drop_uninit_slot<T: Transfer>(constructor: Constructor<T>) {
+    let drops = DropCounter::new();
+    {
+        crate::slot!(slot);
+        construct(constructor, slot, &drops);
+        {
+            crate::slot!(_uninit: T);
+        }
+        drops.assert_count(0);
+    }
+    drops.assert_count(1);
+}
+
+/// Generates a test for each check of the [`testing`](crate::testing) module.
+///
+/// `check_transfer!(Type, constructor)` takes a [`Constructor`](crate::testing::Constructor),
+/// and `check_transfer!(Type, constructor, observe)` also takes a `fn(&Type) -> O`, used to check
+/// that transfers preserve the value. The constructor must build equal values on each call.
+///
+/// The tests are named after the checks, so the macro should be invoked in its own module.
+#[macro_export]
+macro_rules! check_transfer {
+    ($ty:ty, $constructor:expr) => {
+        $crate::check_transfer!($ty, $constructor, |_: &$ty| ());
+    };
+    ($ty:ty, $constructor:expr, $observe:expr) => {
+        #[test]
+        fn transfer_out() {
+            $crate::testing::transfer_out::<$ty, _>($constructor, $observe)
+        }
+
+        #[test]
+        fn transfer_in() {
+            $crate::testing::transfer_in::<$ty, _>($constructor, $observe)
+        }
+
+        #[test]
+        fn chained_transfers() {
+            $crate::testing::chained_transfers::<$ty, _>($constructor, $observe)
+        }
+
+        #[test]
+        fn drop_source_first() {
+            $crate::testing::drop_source_first::<$ty, _>($constructor, $observe)
+        }
+
+        #[test]
+        fn reuse_slot() {
+            $crate::testing::reuse_slot::<$ty, _>($constructor, $observe)
+        }
+
+        #[test]
+        fn drop_uninit_slot() {
+            $crate::testing::drop_uninit_slot::<$ty>($constructor)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DropToken;
+    use crate::{pin_unpin, Transfer};
+    use stackpin::{FromUnpinned, PinStack};
+    use std::ptr;
+
+    #[test]
+    #[should_panic(expected = "the value is not at the address of its slot")]
+    fn constructor_outside_slot() {
+        // Outlives the slot like a leaked box would, without leaking.
+        static mut OUTSIDE: u64 = 1;
+        super::drop_source_first::<u64, _>(
+            |_, _| pin_unpin(unsafe { &mut *ptr::addr_of_mut!(OUTSIDE) }),
+            |value| *value,
+        );
+    }
+
+    /// Leaves its token in the source.
+    struct Forgetful(#[allow(dead_code)] DropToken);
+
+    unsafe impl Transfer for Forgetful {
+        unsafe fn transfer(_src: &mut PinStack<'_, Self>, dst: *mut Self) {
+            ptr::write(dst, Self(DropToken::default()))
+        }
+    }
+
+    unsafe impl FromUnpinned<DropToken> for Forgetful {
+        type PinData = ();
+
+        unsafe fn from_unpinned(token: DropToken) -> (Self, ()) {
+            (Self(token), ())
+        }
+
+        unsafe fn on_pin(&mut self, _: ()) {}
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 0 drops of the value, found 1")]
+    fn source_dropped_the_value() {
+        super::drop_source_first::<Forgetful, _>(|slot, token| slot.emplace(token), |_| ());
+    }
+}
@@ -0,0 +1,385 @@
+//! A growable vector of unmovable elements.
+
+use crate::{guard, pin_ptr, transfer, Tr, Transfer};
+use stackpin::PinStack;
+use std::mem::MaybeUninit;
+use std::pin::Pin;
+use std::ptr;
+
+/// A vector whose elements are pinned, and relocated with `Transfer` when needed.
+///
+/// Elements are transferred, never moved:
+///
+/// * when the vector reallocates, every element is transferred to the new buffer
+/// * `insert` and `remove` transfer the elements after the insertion or removal point by one slot
+/// * `swap_remove` transfers the last element to the removed slot
+///
+/// Reset sources are dropped right after their transfer. If such a destructor panics, the process
+/// aborts, as the elements being relocated could then neither be dropped nor leaked.
+///
+/// Unlike a slot, the vector can be leaked, so that its elements are never dropped. `push` and
+/// `insert` require `T: 'static`, so that values whose drop ends a borrow, such as a
+/// [`Lifetime`](crate::dynref::Lifetime), cannot be stored in the vector:
+///
+/// ```compile_fail,E0597
+/// use stackpin::stack_let;
+/// use transfer::dynref::DynRef;
+/// use transfer::vec::PinVec;
+///
+/// let dr = DynRef::new();
+/// {
+///     let s = String::from("foo");
+///     stack_let!(lifetime = dr.lock(&s));
+///     let mut vec = PinVec::new();
+///     vec.push(lifetime);
+///     std::mem::forget(vec);
+/// }
+/// dr.map(|s| s.len());
+/// ```
+pub struct PinVec<T> {
+    buf: Vec<MaybeUninit<T>>,
+    len: usize,
+}
+
+/// Transfers the value at `src` to the uninitialized `dst`, then drops the reset source.
+unsafe fn relocate<T: Transfer>(src: *mut T, dst: *mut T) {
+    guard::transfer(&mut pin_ptr(src), dst);
+    guard::drop_reset(src);
+}
+
+fn alloc<T>(capacity: usize) -> Vec<MaybeUninit<T>> {
+    let mut buf = Vec::with_capacity(capacity);
+    buf.resize_with(capacity, MaybeUninit::uninit);
+    buf
+}
+
+impl<T> PinVec<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates an empty vector that can hold `capacity` elements before reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: alloc(capacity),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Pin<&T>> {
+        self.buf[..self.len]
+            .get(index)
+            .map(|elem| unsafe { Pin::new_unchecked(&*elem.as_ptr()) })
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<Pin<&mut T>> {
+        self.buf[..self.len]
+            .get_mut(index)
+            .map(|elem| unsafe { Pin::new_unchecked(&mut *elem.as_mut_ptr()) })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Pin<&T>> {
+        self.buf[..self.len]
+            .iter()
+            .map(|elem| unsafe { Pin::new_unchecked(&*elem.as_ptr()) })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = Pin<&mut T>> {
+        self.buf[..self.len]
+            .iter_mut()
+            .map(|elem| unsafe { Pin::new_unchecked(&mut *elem.as_mut_ptr()) })
+    }
+
+    /// Drops all the elements, keeping the capacity.
+    pub fn clear(&mut self) {
+        let len = self.len;
+        // Elements are forgotten rather than dropped twice if a destructor panics
+        self.len = 0;
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.as_mut_ptr(), len));
+        }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        self.buf.as_mut_ptr() as *mut T
+    }
+}
+
+impl<T: Transfer> PinVec<T> {
+    /// Ensures that `additional` more elements can be added without reallocating.
+    ///
+    /// If the vector reallocates, every element is transferred to the new buffer. Otherwise, no
+    /// transfer happens.
+    pub fn reserve(&mut self, additional: usize) {
+        let required = self
+            .len
+            .checked_add(additional)
+            .expect("capacity overflow");
+        if required <= self.capacity() {
+            return;
+        }
+        let capacity = required.max(self.capacity() * 2).max(4);
+        let mut buf = alloc::<T>(capacity);
+        let dst = buf.as_mut_ptr() as *mut T;
+        let src = self.as_mut_ptr();
+        for i in 0..self.len {
+            unsafe { relocate(src.add(i), dst.add(i)) }
+        }
+        // The old buffer only holds reset sources that were already dropped.
+        self.buf = buf;
+    }
+
+    /// Transfers `value` to the end of the vector.
+    pub fn push(&mut self, mut value: PinStack<'_, T>)
+    where
+        T: 'static,
+    {
+        self.reserve(1);
+        unsafe {
+            let dst = self.as_mut_ptr().add(self.len);
+            guard::transfer(&mut value, dst);
+        }
+        self.len += 1;
+    }
+
+    /// Transfers the last element of the vector to `dest`.
+    pub fn pop<'new>(&mut self, dest: Pin<&'new mut Tr<T>>) -> Option<PinStack<'new, T>> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(self.take(self.len, dest))
+    }
+
+    /// Transfers `value` at position `index`, after transferring all the elements after it by one
+    /// slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, mut value: PinStack<'_, T>)
+    where
+        T: 'static,
+    {
+        assert!(index <= self.len, "insertion index out of bounds");
+        self.reserve(1);
+        let base = self.as_mut_ptr();
+        unsafe {
+            for i in (index..self.len).rev() {
+                relocate(base.add(i), base.add(i + 1));
+            }
+            guard::transfer(&mut value, base.add(index));
+        }
+        self.len += 1;
+    }
+
+    /// Transfers the element at position `index` to `dest`, then transfers all the elements after
+    /// it by one slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn remove<'new>(&mut self, index: usize, dest: Pin<&'new mut Tr<T>>) -> PinStack<'new, T> {
+        assert!(index < self.len, "removal index out of bounds");
+        let removed = self.take(index, dest);
+        let base = self.as_mut_ptr();
+        unsafe {
+            for i in index + 1..self.len {
+                relocate(base.add(i), base.add(i - 1));
+            }
+        }
+        self.len -= 1;
+        removed
+    }
+
+    /// Transfers the element at position `index` to `dest`, then transfers the last element to
+    /// position `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn swap_remove<'new>(
+        &mut self,
+        index: usize,
+        dest: Pin<&'new mut Tr<T>>,
+    ) -> PinStack<'new, T> {
+        assert!(index < self.len, "swap_remove index out of bounds");
+        let removed = self.take(index, dest);
+        let last = self.len - 1;
+        if index != last {
+            let base = self.as_mut_ptr();
+            unsafe { relocate(base.add(last), base.add(index)) }
+        }
+        self.len -= 1;
+        removed
+    }
+
+    /// Transfers the element at `index` to `dest` and drops the reset source, leaving the slot
+    /// uninitialized.
+    fn take<'new>(&mut self, index: usize, dest: Pin<&'new mut Tr<T>>) -> PinStack<'new, T> {
+        unsafe {
+            let src = self.as_mut_ptr().add(index);
+            let taken = transfer(pin_ptr(src), dest);
+            guard::drop_reset(src);
+            taken
+        }
+    }
+}
+
+impl<T> Default for PinVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for PinVec<T> {
+    fn drop(&mut self) {
+        self.clear()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PinVec;
+    use crate::fixtures::Tracked;
+    use stackpin::stack_let;
+
+    fn push(vec: &mut PinVec<Tracked>, value: u32) {
+        stack_let!(tracked: Tracked = value);
+        vec.push(tracked);
+    }
+
+    fn values(vec: &PinVec<Tracked>) -> Vec<u32> {
+        vec.iter().map(|tracked| tracked.check()).collect()
+    }
+
+    #[test]
+    fn push_and_grow() {
+        let mut vec = PinVec::new();
+        for value in 1..=10 {
+            push(&mut vec, value);
+        }
+        assert_eq!(values(&vec), (1..=10).collect::<Vec<_>>());
+        assert!(vec.capacity() >= 10);
+    }
+
+    #[test]
+    fn reserve_does_not_transfer_when_capacity_suffices() {
+        let mut vec = PinVec::with_capacity(4);
+        push(&mut vec, 1);
+        let before = vec.get(0).unwrap().this;
+        vec.reserve(3);
+        assert_eq!(vec.get(0).unwrap().this, before);
+        vec.reserve(4);
+        assert_ne!(vec.get(0).unwrap().this, before);
+        assert_eq!(values(&vec), [1]);
+    }
+
+    #[test]
+    fn insert_remove() {
+        let mut vec = PinVec::new();
+        for value in 1..=4 {
+            push(&mut vec, value);
+        }
+        stack_let!(tracked: Tracked = 10);
+        vec.insert(1, tracked);
+        assert_eq!(values(&vec), [1, 10, 2, 3, 4]);
+
+        crate::slot!(slot);
+        let removed = vec.remove(3, slot);
+        assert_eq!(removed.check(), 3);
+        assert_eq!(values(&vec), [1, 10, 2, 4]);
+
+        crate::slot!(slot);
+        let removed = vec.swap_remove(0, slot);
+        assert_eq!(removed.check(), 1);
+        assert_eq!(values(&vec), [4, 10, 2]);
+
+        crate::slot!(slot);
+        let popped = vec.pop(slot).unwrap();
+        assert_eq!(popped.check(), 2);
+        assert_eq!(values(&vec), [4, 10]);
+    }
+
+    #[test]
+    fn iter_mut() {
+        let mut vec = PinVec::new();
+        for value in 1..=3 {
+            push(&mut vec, value);
+        }
+        for tracked in vec.iter_mut() {
+            unsafe { tracked.get_unchecked_mut().value *= 2 }
+        }
+        assert_eq!(values(&vec), [2, 4, 6]);
+    }
+
+    mod panicking_drop {
+        use super::PinVec;
+        use crate::fixtures::{abort_child, aborted_stderr};
+        use crate::{pin_unpin, TriviallyTransferable};
+        use std::cell::Cell;
+
+        thread_local! {
+            static ARMED: Cell<bool> = const { Cell::new(false) };
+        }
+
+        /// Panics when dropping a reset source once armed.
+        #[derive(Default)]
+        struct Fragile(u32);
+
+        impl TriviallyTransferable for Fragile {}
+
+        impl Drop for Fragile {
+            fn drop(&mut self) {
+                if self.0 == 0 && ARMED.with(|armed| armed.replace(false)) {
+                    panic!("reset source dropped");
+                }
+            }
+        }
+
+        fn run(operation: &str) {
+            let mut vec = PinVec::with_capacity(3);
+            for value in 1..=3 {
+                vec.push(pin_unpin(&mut Fragile(value)));
+            }
+            ARMED.with(|armed| armed.set(true));
+            crate::slot!(mut slot);
+            match operation {
+                "reserve" => vec.reserve(4),
+                "insert" => vec.insert(1, pin_unpin(&mut Fragile(4))),
+                "remove" => drop(vec.remove(1, slot.as_mut())),
+                "swap_remove" => drop(vec.swap_remove(0, slot.as_mut())),
+                _ => unreachable!(),
+            }
+        }
+
+        /// Runs each operation in a child process, as dropping the reset source aborts.
+        #[test]
+        #[cfg_attr(miri, ignore)]
+        fn aborts() {
+            if let Some(operation) = abort_child() {
+                run(&operation);
+                return;
+            }
+            for operation in ["reserve", "insert", "remove", "swap_remove"] {
+                let stderr = aborted_stderr("vec::tests::panicking_drop::aborts", operation);
+                assert!(stderr.contains("reset source dropped"));
+                if cfg!(feature = "debug-abort") {
+                    assert!(stderr.contains("drop of the reset `transfer::vec::tests::"));
+                }
+            }
+        }
+    }
+}
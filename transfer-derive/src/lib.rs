@@ -0,0 +1,374 @@
+//! Procedural macros for the [`transfer`](https://docs.rs/transfer) crate.
+//!
+//! This crate is re-exported by `transfer` when its `derive` feature is enabled.
+
+extern crate proc_macro;
+
+mod returns_pinned;
+
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote, quote_spanned};
+use syn::parse::ParseStream;
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DataEnum, DataStruct, DeriveInput, Error,
+    Expr, Fields, Ident, Index, ItemFn, Member, Token, Type, WherePredicate,
+};
+
+/// Derives `Transfer` by transferring the type field by field.
+///
+/// Fields are transferred with their own `Transfer` implementation, unless they are annotated:
+///
+/// * `#[transfer(move)]` moves an `Unpin` field to the destination, and resets the source field
+///   to `Default::default()`.
+/// * `#[transfer(reset = expr)]` moves an `Unpin` field to the destination, and resets the source
+///   field to `expr`.
+///
+/// `is_reset` holds when all the transferred fields are reset; moved fields are not checked. For
+/// structs without moved fields, `NO_PADDING` holds when it holds for all the fields and their
+/// sizes add up to the size of the struct. It is never set for enums.
+#[proc_macro_derive(Transfer, attributes(transfer))]
+pub fn derive_transfer(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+/// Lets a function return a pinned value to a slot provided by its caller.
+///
+/// The function is declared as returning `T`, and its body evaluates to a `PinStack<'_, T>`.
+/// The attribute appends a `Pin<&mut Tr<T>>` slot parameter to the function, and transfers the
+/// value to the slot, so that the function returns a `PinStack` to the slot:
+///
+/// ```ignore
+/// #[returns_pinned]
+/// fn generate_secret() -> SecretU64 {
+///     let mut secret = 42;
+///     stack_let!(secret: SecretU64 = &mut secret);
+///     secret
+/// }
+///
+/// transfer_let!(secret = generate_secret());
+/// ```
+#[proc_macro_attribute]
+pub fn returns_pinned(
+    args: proc_macro::TokenStream,
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    if let Some(arg) = TokenStream::from(args).into_iter().next() {
+        return Error::new(arg.span(), "`#[returns_pinned]` does not take arguments")
+            .to_compile_error()
+            .into();
+    }
+    let input = parse_macro_input!(input as ItemFn);
+    returns_pinned::expand(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+enum Strategy {
+    Transfer,
+    Move(Option<Box<Expr>>),
+}
+
+struct Field {
+    member: Member,
+    ty: Type,
+    strategy: Strategy,
+}
+
+/// The parts of a derived implementation that depend on the kind of type.
+struct Expansion {
+    transfer: TokenStream,
+    is_reset: TokenStream,
+    /// `None` keeps the default of the trait.
+    no_padding: Option<TokenStream>,
+    fields: Vec<Field>,
+}
+
+impl Field {
+    /// Statement moving or transferring `src`, a `&mut` to the field, to `dst`, a `*mut` to the
+    /// field.
+    fn transfer(&self, src: &TokenStream, dst: &TokenStream) -> TokenStream {
+        let ty = &self.ty;
+        match &self.strategy {
+            Strategy::Transfer => quote_spanned! {ty.span()=>
+                <#ty as ::transfer::Transfer>::transfer(
+                    &mut ::transfer::__private::pin_field(#src),
+                    #dst,
+                );
+            },
+            Strategy::Move(reset) => {
+                let reset = match reset {
+                    Some(reset) => quote!(#reset),
+                    None => quote!(::core::default::Default::default()),
+                };
+                quote_spanned! {ty.span()=>
+                    ::core::ptr::write(#dst, ::transfer::__private::move_field::<#ty>(#src, #reset));
+                }
+            }
+        }
+    }
+
+    /// Expression checking that `field`, a `&` to the field, is reset, or `None` for moved fields.
+    fn is_reset(&self, field: &TokenStream) -> Option<TokenStream> {
+        let ty = &self.ty;
+        match &self.strategy {
+            Strategy::Transfer => Some(quote_spanned! {ty.span()=>
+                <#ty as ::transfer::Transfer>::is_reset(#field)
+            }),
+            Strategy::Move(_) => None,
+        }
+    }
+
+    fn bounds(&self) -> Vec<WherePredicate> {
+        let ty = &self.ty;
+        match &self.strategy {
+            Strategy::Transfer => vec![parse_quote!(#ty: ::transfer::Transfer)],
+            Strategy::Move(Some(_)) => vec![parse_quote!(#ty: ::core::marker::Unpin)],
+            Strategy::Move(None) => vec![
+                parse_quote!(#ty: ::core::marker::Unpin),
+                parse_quote!(#ty: ::core::default::Default),
+            ],
+        }
+    }
+}
+
+fn expand(mut input: DeriveInput) -> syn::Result<TokenStream> {
+    reject_transfer_attrs(&input.attrs)?;
+    // Hygienic, so that the expressions of `reset` attributes cannot refer to them.
+    let src = Ident::new("src", Span::mixed_site());
+    let dst = Ident::new("dst", Span::mixed_site());
+    let expansion = match &input.data {
+        Data::Struct(data) => expand_struct(data, &src, &dst)?,
+        Data::Enum(data) => expand_enum(data, &src, &dst)?,
+        Data::Union(data) => {
+            return Err(Error::new(
+                data.union_token.span,
+                "`Transfer` cannot be derived for unions",
+            ))
+        }
+    };
+
+    // Bounds on the field types are only needed for generic types: for other types, missing
+    // implementations are reported on the field itself.
+    if input.generics.type_params().next().is_some() {
+        let where_clause = input.generics.make_where_clause();
+        for field in &expansion.fields {
+            where_clause.predicates.extend(field.bounds());
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let transfer_body = &expansion.transfer;
+    let is_reset = &expansion.is_reset;
+    let no_padding = expansion
+        .no_padding
+        .as_ref()
+        .map(|no_padding| quote!(const NO_PADDING: bool = #no_padding;));
+    Ok(quote! {
+        unsafe impl #impl_generics ::transfer::Transfer for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            unsafe fn transfer(
+                #src: &mut ::transfer::__private::PinStack<'_, Self>,
+                #dst: *mut Self,
+            ) {
+                unsafe {
+                    let #src = ::core::pin::Pin::get_unchecked_mut(::core::pin::Pin::as_mut(#src));
+                    #transfer_body
+                }
+            }
+
+            fn is_reset(&self) -> bool {
+                #is_reset
+            }
+
+            #no_padding
+        }
+    })
+}
+
+/// Joins `exprs` with `op`, or returns `empty` if there are none.
+fn join(exprs: Vec<TokenStream>, op: TokenStream, empty: TokenStream) -> TokenStream {
+    let mut exprs = exprs.into_iter();
+    match exprs.next() {
+        Some(first) => exprs.fold(first, |joined, expr| quote!(#joined #op #expr)),
+        None => empty,
+    }
+}
+
+fn expand_struct(data: &DataStruct, src: &Ident, dst: &Ident) -> syn::Result<Expansion> {
+    let fields = parse_fields(&data.fields)?;
+
+    let transfers = fields.iter().map(|field| {
+        let member = &field.member;
+        field.transfer(
+            &quote!(&mut #src.#member),
+            &quote!(::core::ptr::addr_of_mut!((*#dst).#member)),
+        )
+    });
+    let transfer = quote!(#(#transfers)*);
+
+    let is_reset = fields
+        .iter()
+        .filter_map(|field| {
+            let member = &field.member;
+            field.is_reset(&quote!(&self.#member))
+        })
+        .collect();
+    let is_reset = join(is_reset, quote!(&&), quote!(true));
+
+    // Without padding, the bytes of the fields cover the whole struct. The bytes of moved fields
+    // are unknown.
+    let no_padding = if fields
+        .iter()
+        .all(|field| matches!(field.strategy, Strategy::Transfer))
+    {
+        let tys: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+        let all_no_padding = tys
+            .iter()
+            .map(|ty| quote!(<#ty as ::transfer::Transfer>::NO_PADDING))
+            .collect();
+        let all_no_padding = join(all_no_padding, quote!(&&), quote!(true));
+        let size = tys
+            .iter()
+            .map(|ty| quote!(::core::mem::size_of::<#ty>()))
+            .collect();
+        let size = join(size, quote!(+), quote!(0));
+        Some(quote!(#all_no_padding && #size == ::core::mem::size_of::<Self>()))
+    } else {
+        None
+    };
+
+    Ok(Expansion {
+        transfer,
+        is_reset,
+        no_padding,
+        fields,
+    })
+}
+
+fn expand_enum(data: &DataEnum, src: &Ident, dst: &Ident) -> syn::Result<Expansion> {
+    let mut all_fields = Vec::new();
+    let mut arms = Vec::new();
+    let mut is_reset_arms = Vec::new();
+
+    for variant in &data.variants {
+        let ident = &variant.ident;
+        reject_transfer_attrs(&variant.attrs)?;
+        let fields = parse_fields(&variant.fields)?;
+
+        let members: Vec<_> = fields.iter().map(|field| &field.member).collect();
+        let srcs: Vec<_> = (0..fields.len())
+            .map(|i| format_ident!("__src_{}", i, span = Span::mixed_site()))
+            .collect();
+        let dsts: Vec<_> = (0..fields.len())
+            .map(|i| format_ident!("__dst_{}", i, span = Span::mixed_site()))
+            .collect();
+        let transfers = fields
+            .iter()
+            .zip(srcs.iter().zip(&dsts))
+            .map(|(field, (src, dst))| field.transfer(&quote!(#src), &quote!(#dst)));
+        let is_reset = fields
+            .iter()
+            .zip(&srcs)
+            .filter_map(|(field, src)| field.is_reset(&quote!(#src)))
+            .collect();
+        let is_reset = join(is_reset, quote!(&&), quote!(true));
+
+        arms.push(quote! {
+            Self::#ident { #(#members: #srcs),* } => match &mut *#dst {
+                Self::#ident { #(#members: #dsts),* } => { #(#transfers)* }
+                #[allow(unreachable_patterns)]
+                _ => ::core::hint::unreachable_unchecked(),
+            },
+        });
+        is_reset_arms.push(quote! {
+            Self::#ident { #(#members: #srcs),* } => #is_reset,
+        });
+        all_fields.extend(fields);
+    }
+
+    // The source is first copied bitwise to the destination, so that the destination holds the
+    // right variant. Each field of the copy is then overwritten without being dropped.
+    let transfer = quote! {
+        ::core::ptr::copy_nonoverlapping(#src as *const Self, #dst, 1);
+        match #src {
+            #(#arms)*
+        }
+    };
+    let is_reset = quote! {
+        #[allow(unused_variables)]
+        match self {
+            #(#is_reset_arms)*
+        }
+    };
+
+    // The discriminant, and the bytes that a variant does not use, are not written by the
+    // transfers of the fields.
+    Ok(Expansion {
+        transfer,
+        is_reset,
+        no_padding: None,
+        fields: all_fields,
+    })
+}
+
+fn parse_fields(fields: &Fields) -> syn::Result<Vec<Field>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let member = match &field.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(Index::from(i)),
+            };
+            Ok(Field {
+                member,
+                ty: field.ty.clone(),
+                strategy: parse_field_attrs(&field.attrs)?,
+            })
+        })
+        .collect()
+}
+
+fn transfer_attrs(attrs: &[Attribute]) -> impl Iterator<Item = &Attribute> {
+    attrs.iter().filter(|attr| attr.path.is_ident("transfer"))
+}
+
+/// `#[transfer]` only applies to fields, and is rejected on containers and variants.
+fn reject_transfer_attrs(attrs: &[Attribute]) -> syn::Result<()> {
+    match transfer_attrs(attrs).next() {
+        Some(attr) => Err(Error::new_spanned(
+            attr,
+            "`transfer` attributes are only allowed on fields",
+        )),
+        None => Ok(()),
+    }
+}
+
+fn parse_field_attrs(attrs: &[Attribute]) -> syn::Result<Strategy> {
+    let mut strategy = None;
+    for attr in transfer_attrs(attrs) {
+        let parsed = attr.parse_args_with(|input: ParseStream| {
+            if input.parse::<Option<Token![move]>>()?.is_some() {
+                return Ok(Strategy::Move(None));
+            }
+            let ident: Ident = input.parse()?;
+            if ident != "reset" {
+                return Err(Error::new(
+                    ident.span(),
+                    "expected `move` or `reset = ...`",
+                ));
+            }
+            input.parse::<Token![=]>()?;
+            Ok(Strategy::Move(Some(input.parse()?)))
+        })?;
+        if strategy.replace(parsed).is_some() {
+            return Err(Error::new_spanned(attr, "duplicate `transfer` attribute"));
+        }
+    }
+    Ok(strategy.unwrap_or(Strategy::Transfer))
+}
@@ -0,0 +1,77 @@
+//! Expansion of the `#[returns_pinned]` attribute.
+
+use proc_macro2::{Span, TokenStream};
+use quote::ToTokens;
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    parse_quote, Error, Expr, ExprAsync, ExprClosure, ExprReturn, Ident, Item, ItemFn, Lifetime,
+    ReturnType, Stmt,
+};
+
+/// Rewrites `fn f(args) -> T { body }`, whose body returns a `PinStack<'_, T>`, to
+/// `fn f<'pinned>(args, slot: Pin<&'pinned mut Tr<T>>) -> PinStack<'pinned, T>`, transferring
+/// the returned value to `slot`.
+pub(crate) fn expand(mut item: ItemFn) -> syn::Result<TokenStream> {
+    let sig = &mut item.sig;
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(Error::new(
+            asyncness.span,
+            "`#[returns_pinned]` cannot be applied to async functions",
+        ));
+    }
+    let ty = match &sig.output {
+        ReturnType::Type(_, ty) => ty.clone(),
+        ReturnType::Default => {
+            return Err(Error::new_spanned(
+                sig.fn_token,
+                "`#[returns_pinned]` expects a function returning the pinned type",
+            ))
+        }
+    };
+
+    let lifetime = Lifetime::new("'__pinned", Span::mixed_site());
+    let slot = Ident::new("__slot", Span::mixed_site());
+    sig.generics.params.insert(0, parse_quote!(#lifetime));
+    sig.inputs.push(parse_quote! {
+        #slot: ::core::pin::Pin<&#lifetime mut ::transfer::Tr<#ty>>
+    });
+    sig.output = parse_quote!(-> ::transfer::__private::PinStack<#lifetime, #ty>);
+
+    let mut returns = TransferReturns { slot: &slot };
+    returns.visit_block_mut(&mut item.block);
+    if let Some(Stmt::Expr(tail)) = item.block.stmts.last_mut() {
+        *tail = returns.transfer(tail);
+    }
+
+    Ok(item.into_token_stream())
+}
+
+/// Transfers the values returned by the function to the slot.
+struct TransferReturns<'a> {
+    slot: &'a Ident,
+}
+
+impl TransferReturns<'_> {
+    fn transfer(&self, value: &Expr) -> Expr {
+        let slot = self.slot;
+        parse_quote! {
+            ::transfer::transfer(::transfer::__private::IntoPinStack::into_pin_stack(#value), #slot)
+        }
+    }
+}
+
+impl VisitMut for TransferReturns<'_> {
+    fn visit_expr_return_mut(&mut self, ret: &mut ExprReturn) {
+        visit_mut::visit_expr_return_mut(self, ret);
+        if let Some(value) = &mut ret.expr {
+            **value = self.transfer(value);
+        }
+    }
+
+    // `return` in closures, async blocks and nested items does not return from the function.
+    fn visit_expr_closure_mut(&mut self, _: &mut ExprClosure) {}
+
+    fn visit_expr_async_mut(&mut self, _: &mut ExprAsync) {}
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+}